@@ -1,36 +1,107 @@
-#[global_allocator]
-static ALLOCATOR: BumpAllocator = BumpAllocator;
+// The heap allocator is picked at compile time with the `free_list_alloc` feature, off by default:
+// - `BumpAllocator` only moves a pointer up, so it is the fastest, but it reclaims nothing until
+//   every allocation is freed. Fine for code that allocates once and keeps everything.
+// - `FreeListAllocator` (the `free_list_alloc` feature) reuses every freed block and merges
+//   neighbouring free blocks, so the game can allocate and free text every frame for as long as
+//   it runs. Each allocation walks the free list, which costs a little more. The game's boot
+//   image turns it on, see the top-level `Cargo.toml`.
+// Host tests build both, to test them.
+#[cfg(any(test, not(feature = "free_list_alloc")))]
+mod bump;
+#[cfg(any(test, feature = "free_list_alloc"))]
+mod free_list;
+
+#[cfg(not(feature = "free_list_alloc"))]
+type Heap = bump::BumpAllocator;
+#[cfg(feature = "free_list_alloc")]
+type Heap = free_list::FreeListAllocator;
+
+#[cfg_attr(not(test), global_allocator)] // host tests keep the system allocator
+static ALLOCATOR: Heap = Heap::new();
 
 use alloc::alloc::{GlobalAlloc, Layout};
+use core::fmt;
 use core::fmt::Write;
+use kernel::{hlt_loop, serial};
+
+pub static mut HEAP_SIZE: usize = 0x0; // set from the usable memory region at boot
 
-use crate::serial;
+/// What the rest of the kernel needs from an allocator besides `GlobalAlloc`.
+trait KernelHeap: GlobalAlloc {
+    /// Hands the `size` bytes starting at `offset` to the allocator.
+    ///
+    /// ## Safety
+    /// The memory must be mapped, unused and not handed to the allocator before.
+    unsafe fn init(&self, offset: usize, size: usize);
 
-pub static mut HEAP_START: usize = 0x0;
-pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+    /// Bytes of the heap that cannot be handed out right now.
+    fn bytes_used(&self) -> usize;
 
-pub struct BumpAllocator;
+    /// The largest `bytes_used` seen since boot.
+    fn high_water_mark(&self) -> usize;
+
+    /// Bytes of the heap that can still be handed out, possibly split over several blocks.
+    fn bytes_free(&self) -> usize {
+        unsafe { HEAP_SIZE }.saturating_sub(self.bytes_used())
+    }
+}
 
-unsafe impl GlobalAlloc for BumpAllocator {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let bump_ptr = HEAP_START;
-        let new_heap_start = HEAP_START + layout.size();
+/// Snapshot of the heap usage, see `stats`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub bytes_used: usize,
+    pub bytes_free: usize,
+    pub high_water_mark: usize,
+}
 
-        if new_heap_start > HEAP_START + HEAP_SIZE {
-            panic!("Out of memory!");
-        }
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes used, {} free, at most {} used so far",
+            self.bytes_used, self.bytes_free, self.high_water_mark
+        )
+    }
+}
 
-        HEAP_START = new_heap_start;
-        bump_ptr as *mut u8
+/// Returns the current usage of the kernel heap.
+pub fn stats() -> HeapStats {
+    HeapStats {
+        bytes_used: ALLOCATOR.bytes_used(),
+        bytes_free: ALLOCATOR.bytes_free(),
+        high_water_mark: ALLOCATOR.high_water_mark(),
     }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        writeln!(serial(), "dealloc was called at {_ptr:?}").unwrap();
+/// Called when an allocation that cannot fail, such as `Vec::push`, gets no memory. Reports
+/// the request and the heap usage over serial and on screen, then halts.
+#[cfg(not(test))]
+#[alloc_error_handler]
+fn out_of_memory(layout: Layout) -> ! {
+    let _ = writeln!(
+        serial(),
+        "OUT OF MEMORY: {} bytes aligned to {} requested, {}",
+        layout.size(),
+        layout.align(),
+        stats()
+    );
+    // Drawing text needs no heap, so the message can still be shown
+    if let Some(writer) = crate::screen::try_screenwriter() {
+        let y = writer.height() / 2;
+        writer.draw_centered_text("Out of memory - see the serial log", y);
+        writer.present();
     }
+    hlt_loop();
 }
 
-pub fn init_heap(offset: usize) {
+/// Hands the `size` bytes starting at virtual address `offset` to the allocator.
+pub fn init_heap(offset: usize, size: usize) {
     unsafe {
-        HEAP_START = offset;
+        HEAP_SIZE = size;
+        ALLOCATOR.init(offset, size);
     }
 }
@@ -0,0 +1,182 @@
+use super::{KernelHeap, align_up};
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+struct Bump {
+    start: usize,
+    end: usize,
+    next: usize,        // first byte not handed out yet
+    allocations: usize, // blocks handed out and not freed yet
+}
+
+/// Hands out the heap from the bottom up and never reuses a single freed block: the whole heap
+/// only becomes free again once every allocation has been freed. Allocating is a few additions,
+/// the cheapest there is, which suits code that allocates once and keeps what it got.
+pub struct BumpAllocator {
+    bump: Mutex<Bump>,
+    high_water: AtomicUsize,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        Self {
+            bump: Mutex::new(Bump {
+                start: 0,
+                end: 0,
+                next: 0,
+                allocations: 0,
+            }),
+            high_water: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl KernelHeap for BumpAllocator {
+    unsafe fn init(&self, offset: usize, size: usize) {
+        let mut bump = self.bump.lock();
+        bump.start = offset;
+        bump.end = offset + size;
+        bump.next = offset;
+    }
+
+    /// Bytes from the start of the heap to the next allocation, freed blocks below it included.
+    fn bytes_used(&self) -> usize {
+        let bump = self.bump.lock();
+        bump.next - bump.start
+    }
+
+    fn high_water_mark(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    /// Returns a null pointer once the heap is used up, as `GlobalAlloc` requires.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut bump = self.bump.lock();
+        let start = align_up(bump.next, layout.align());
+        match start.checked_add(layout.size()) {
+            Some(end) if end <= bump.end => {
+                bump.next = end;
+                bump.allocations += 1;
+                self.high_water
+                    .fetch_max(end - bump.start, Ordering::Relaxed);
+                start as *mut u8
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        let mut bump = self.bump.lock();
+        bump.allocations -= 1;
+        if bump.allocations == 0 {
+            bump.next = bump.start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// A bump allocator over a buffer of its own, so the test itself runs on the system
+    /// allocator.
+    struct TestHeap {
+        allocator: BumpAllocator,
+        _buffer: Vec<u64>, // the heap; `u64`s keep its start aligned
+    }
+
+    impl TestHeap {
+        fn new(size: usize) -> Self {
+            let buffer = vec![0u64; size / 8];
+            let allocator = BumpAllocator::new();
+            unsafe { allocator.init(buffer.as_ptr() as usize, size) };
+            Self {
+                allocator,
+                _buffer: buffer,
+            }
+        }
+    }
+
+    #[test]
+    fn allocations_are_aligned_as_requested() {
+        let heap = TestHeap::new(4096);
+        for size in [1, 3, 7, 13] {
+            // An odd size first, so the next free byte is not aligned
+            let odd = Layout::from_size_align(size, 1).unwrap();
+            assert!(!unsafe { heap.allocator.alloc(odd) }.is_null());
+            for align in [16, 64] {
+                let layout = Layout::from_size_align(24, align).unwrap();
+                let ptr = unsafe { heap.allocator.alloc(layout) };
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0, "{size} bytes, then align {align}");
+            }
+        }
+    }
+
+    #[test]
+    fn used_up_heap_returns_null() {
+        let heap = TestHeap::new(256);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        for _ in 0..4 {
+            assert!(!unsafe { heap.allocator.alloc(layout) }.is_null());
+        }
+        assert!(unsafe { heap.allocator.alloc(layout) }.is_null());
+        let huge = Layout::from_size_align(isize::MAX as usize - 7, 8).unwrap();
+        assert!(
+            unsafe { heap.allocator.alloc(huge) }.is_null(),
+            "far larger than the heap"
+        );
+    }
+
+    #[test]
+    fn heap_starts_over_once_everything_is_freed() {
+        let heap = TestHeap::new(256);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let first = heap.allocator.alloc(layout);
+            let second = heap.allocator.alloc(layout);
+            heap.allocator.dealloc(first, layout);
+            assert_eq!(
+                heap.allocator.bytes_used(),
+                128,
+                "freed while another block is in use"
+            );
+            heap.allocator.dealloc(second, layout);
+            assert_eq!(heap.allocator.bytes_used(), 0);
+            assert_eq!(heap.allocator.alloc(layout), first);
+        }
+    }
+
+    #[test]
+    fn usage_and_high_water_mark_follow_allocations() {
+        let heap = TestHeap::new(256);
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        assert_eq!(
+            (
+                heap.allocator.bytes_used(),
+                heap.allocator.high_water_mark()
+            ),
+            (0, 0)
+        );
+        unsafe {
+            let pointers: Vec<_> = (0..3).map(|_| heap.allocator.alloc(layout)).collect();
+            assert_eq!(heap.allocator.bytes_used(), 120);
+            for ptr in pointers {
+                heap.allocator.dealloc(ptr, layout);
+            }
+            assert_eq!(heap.allocator.bytes_used(), 0);
+            heap.allocator.alloc(layout);
+        }
+        assert_eq!(heap.allocator.bytes_used(), 40);
+        assert_eq!(
+            heap.allocator.high_water_mark(),
+            120,
+            "the most used at once"
+        );
+    }
+}
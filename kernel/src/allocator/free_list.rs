@@ -0,0 +1,377 @@
+use super::{KernelHeap, align_up};
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Header stored in the first bytes of every free block. Free blocks form a singly linked list
+/// sorted by address, so a freed block can be merged with its neighbours.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Every block has to be able to hold a `FreeBlock` header once it is freed again.
+const MIN_BLOCK_SIZE: usize = mem::size_of::<FreeBlock>();
+
+struct FreeList {
+    head: *mut FreeBlock,
+}
+
+// The list is only ever touched behind the allocator's lock.
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    /// Adds the block `[addr, addr + size)` to the list, merging it with the free blocks
+    /// directly before and after it.
+    ///
+    /// ## Safety
+    /// The block must be unused, at least `MIN_BLOCK_SIZE` bytes long and aligned for
+    /// `FreeBlock`.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        unsafe {
+            while !next.is_null() && (next as usize) < addr {
+                prev = next;
+                next = (*next).next;
+            }
+
+            let block = addr as *mut FreeBlock;
+            block.write(FreeBlock { size, next });
+
+            if !next.is_null() && addr + size == next as usize {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+
+            if prev.is_null() {
+                self.head = block;
+            } else if prev as usize + (*prev).size == addr {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else {
+                (*prev).next = block;
+            }
+        }
+    }
+
+    /// Takes the first free block that fits `size` bytes at `align`, splitting off whatever is
+    /// left before and after the allocation as new free blocks. Returns the start of the
+    /// allocation, which is always a multiple of `align`.
+    ///
+    /// ## Safety
+    /// Every block on the list must be valid.
+    unsafe fn take(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+        unsafe {
+            while !current.is_null() {
+                let start = current as usize;
+                let end = start + (*current).size;
+                let next = (*current).next;
+
+                let mut alloc_start = align_up(start, align);
+                if alloc_start != start && alloc_start - start < MIN_BLOCK_SIZE {
+                    // The padding in front would be too small to stay on the free list and
+                    // could never be reclaimed, so move on to the next aligned address.
+                    alloc_start = align_up(start + MIN_BLOCK_SIZE, align);
+                }
+                let alloc_end = alloc_start.checked_add(size)?;
+                let excess = end.saturating_sub(alloc_end);
+                if alloc_end <= end && (excess == 0 || excess >= MIN_BLOCK_SIZE) {
+                    let after = if excess > 0 {
+                        let rest = alloc_end as *mut FreeBlock;
+                        rest.write(FreeBlock { size: excess, next });
+                        rest
+                    } else {
+                        next
+                    };
+                    if alloc_start > start {
+                        // Keep the alignment padding as a smaller free block
+                        (*current).size = alloc_start - start;
+                        (*current).next = after;
+                    } else if prev.is_null() {
+                        self.head = after;
+                    } else {
+                        (*prev).next = after;
+                    }
+                    debug_assert_eq!(alloc_start % align, 0);
+                    return Some(alloc_start);
+                }
+
+                prev = current;
+                current = next;
+            }
+        }
+        None
+    }
+
+    /// Grows the allocated block `[addr, addr + size)` to `new_size` bytes in place, if the
+    /// free block right after it is big enough. Returns whether it did.
+    ///
+    /// ## Safety
+    /// Every block on the list must be valid, and `new_size` must be larger than `size`.
+    unsafe fn grow(&mut self, addr: usize, size: usize, new_size: usize) -> bool {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+        unsafe {
+            while !current.is_null() && (current as usize) < addr + size {
+                prev = current;
+                current = (*current).next;
+            }
+            if current as usize != addr + size {
+                return false; // the next block is in use, or there is none
+            }
+            let needed = new_size - size;
+            let excess = match (*current).size.checked_sub(needed) {
+                Some(excess) if excess == 0 || excess >= MIN_BLOCK_SIZE => excess,
+                _ => return false,
+            };
+            let next = (*current).next;
+            let after = if excess > 0 {
+                let rest = (addr + new_size) as *mut FreeBlock;
+                rest.write(FreeBlock { size: excess, next });
+                rest
+            } else {
+                next
+            };
+            if prev.is_null() {
+                self.head = after;
+            } else {
+                (*prev).next = after;
+            }
+        }
+        true
+    }
+}
+
+/// First-fit allocator over a linked list of free blocks. Freed blocks are merged with adjacent
+/// free blocks so the heap does not fragment under allocation churn.
+pub struct FreeListAllocator {
+    list: Mutex<FreeList>,
+    used: AtomicUsize,
+    high_water: AtomicUsize,
+}
+
+impl FreeListAllocator {
+    pub const fn new() -> Self {
+        Self {
+            list: Mutex::new(FreeList {
+                head: ptr::null_mut(),
+            }),
+            used: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl KernelHeap for FreeListAllocator {
+    unsafe fn init(&self, offset: usize, size: usize) {
+        unsafe { self.list.lock().insert(offset, size) }
+    }
+
+    /// Bytes currently handed out, including the rounding each block is padded with. The
+    /// rest of the heap may be split over several free blocks.
+    fn bytes_used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn high_water_mark(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+}
+
+/// Size and alignment of the block actually reserved for `layout`, so that the block can later
+/// hold a `FreeBlock` header and anything split off after it stays aligned.
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let align = layout.align().max(mem::align_of::<FreeBlock>());
+    let size = align_up(
+        layout.size().max(MIN_BLOCK_SIZE),
+        mem::align_of::<FreeBlock>(),
+    );
+    (size, align)
+}
+
+unsafe impl GlobalAlloc for FreeListAllocator {
+    /// Returns a null pointer if no free block is large enough, as `GlobalAlloc` requires;
+    /// infallible allocations then end up in `out_of_memory`.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+        let addr = unsafe { self.list.lock().take(size, align) };
+        match addr {
+            Some(addr) => {
+                let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+                self.high_water.fetch_max(used, Ordering::Relaxed);
+                addr as *mut u8
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        unsafe { self.list.lock().insert(ptr as usize, size) }
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    /// Resizes the block in place where possible: growing into the free block right after it,
+    /// or shrinking by handing the end back to the free list. Only otherwise is a new block
+    /// allocated and the contents copied, so a `Vec` growing at the end of the heap does not
+    /// leave a trail of copies behind.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let (size, _) = block_layout(layout);
+        let (new_block_size, _) = block_layout(new_layout);
+        let addr = ptr as usize;
+        let resized = if new_block_size > size {
+            unsafe { self.list.lock().grow(addr, size, new_block_size) }
+        } else if size - new_block_size >= MIN_BLOCK_SIZE {
+            unsafe {
+                self.list
+                    .lock()
+                    .insert(addr + new_block_size, size - new_block_size)
+            };
+            true
+        } else {
+            // Too little to free on its own; it would be lost when the smaller block is freed
+            new_block_size == size
+        };
+        if resized {
+            if new_block_size > size {
+                let grown = new_block_size - size;
+                let used = self.used.fetch_add(grown, Ordering::Relaxed) + grown;
+                self.high_water.fetch_max(used, Ordering::Relaxed);
+            } else {
+                self.used
+                    .fetch_sub(size - new_block_size, Ordering::Relaxed);
+            }
+            return ptr;
+        }
+
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::alloc::{AllocError, Allocator};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use core::ptr::NonNull;
+
+    /// A free-list allocator over a buffer of its own, which `Box::new_in` and `Vec::new_in`
+    /// can allocate from while the test itself runs on the system allocator.
+    struct TestHeap {
+        allocator: FreeListAllocator,
+        _buffer: Vec<u64>, // the heap; `u64`s keep it aligned for `FreeBlock`
+    }
+
+    impl TestHeap {
+        fn new(size: usize) -> Self {
+            let buffer = vec![0u64; size / 8];
+            let allocator = FreeListAllocator::new();
+            unsafe { allocator.init(buffer.as_ptr() as usize, size) };
+            Self {
+                allocator,
+                _buffer: buffer,
+            }
+        }
+    }
+
+    fn block(ptr: *mut u8, size: usize) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    unsafe impl Allocator for &TestHeap {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            block(unsafe { self.allocator.alloc(layout) }, layout.size())
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            unsafe { self.allocator.dealloc(ptr.as_ptr(), layout) }
+        }
+
+        // Through `realloc`, which the default would skip by allocating and copying
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<[u8]>, AllocError> {
+            assert_eq!(old_layout.align(), new_layout.align());
+            let ptr = unsafe {
+                self.allocator
+                    .realloc(ptr.as_ptr(), old_layout, new_layout.size())
+            };
+            block(ptr, new_layout.size())
+        }
+    }
+
+    #[test]
+    fn freed_boxes_are_reused() {
+        let heap = TestHeap::new(4096);
+        for i in 0..10_000 {
+            // 128 bytes each, so the heap would be used up after 32 if none were reused
+            let boxed = Box::try_new_in([i; 16], &heap).expect("heap used up");
+            assert_eq!(boxed[15], i);
+        }
+        assert_eq!(heap.allocator.bytes_used(), 0);
+    }
+
+    #[test]
+    fn allocations_are_aligned_as_requested() {
+        let heap = TestHeap::new(4096);
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let aligned = Layout::from_size_align(24, 64).unwrap();
+        unsafe {
+            let first = heap.allocator.alloc(small); // so the free space no longer starts aligned
+            let pointers: Vec<_> = (0..8).map(|_| heap.allocator.alloc(aligned)).collect();
+            for &ptr in &pointers {
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % 64, 0);
+            }
+            for ptr in pointers {
+                heap.allocator.dealloc(ptr, aligned);
+            }
+            heap.allocator.dealloc(first, small);
+        }
+        assert_eq!(heap.allocator.bytes_used(), 0);
+    }
+
+    #[test]
+    fn freed_neighbours_merge_into_one_block() {
+        let heap = TestHeap::new(4096);
+        let boxes: Vec<_> = (0..32)
+            .map(|i| Box::new_in([i as u64; 16], &heap))
+            .collect();
+        drop(boxes);
+        // Only fits if all 32 blocks were merged back into the whole heap
+        assert!(Box::try_new_in([0u8; 4096], &heap).is_ok());
+    }
+
+    #[test]
+    fn growing_vec_stays_in_place() {
+        let heap = TestHeap::new(16 * 1024);
+        let mut vec = Vec::new_in(&heap);
+        for i in 0..3500u32 {
+            // One element at a time, so every push reallocates
+            vec.try_reserve_exact(1).expect("heap used up");
+            vec.push(i);
+        }
+        // Copying into a new block on every push would need the old one too, twice as much
+        let bytes = vec.len() * 4;
+        assert!(heap.allocator.high_water_mark() <= bytes + MIN_BLOCK_SIZE);
+        assert!(vec.iter().copied().eq(0..3500));
+    }
+}
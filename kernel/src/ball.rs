@@ -0,0 +1,178 @@
+use crate::rng::Rng;
+
+/// Largest random change to a serve's vertical speed
+const SERVE_JITTER_Y: f32 = 1.0;
+
+/// The Pong ball. Position and velocity are kept in `f32` so motion stays smooth at any speed;
+/// they are only rounded to whole pixels when the ball is drawn.
+pub struct Ball {
+    pub x: f32,
+    pub y: f32,
+    pub velocity_x: f32, // pixels per tick
+    pub velocity_y: f32, // pixels per tick
+    pub spin: f32,       // added to `velocity_y` every tick, curving the path
+    pub speed: f32,      // length of the velocity set by the last paddle hit or serve
+}
+
+impl Ball {
+    pub const fn new(x: f32, y: f32, velocity_x: f32, velocity_y: f32) -> Self {
+        Self {
+            x,
+            y,
+            velocity_x,
+            velocity_y,
+            spin: 0.0,
+            speed: velocity_x.abs(),
+        }
+    }
+
+    /// Advances the ball by `delta_time` ticks. Spin bends the path: positive spin downward,
+    /// negative spin upward.
+    pub fn update(&mut self, delta_time: f32) {
+        self.velocity_y += self.spin * delta_time;
+        self.x += self.velocity_x * delta_time;
+        self.y += self.velocity_y * delta_time;
+    }
+
+    /// Sends the ball toward `direction` (negative for left, positive for right) at `speed_x`
+    /// pixels per tick, and `speed_y` down plus or minus a little at random. Clears any spin.
+    pub fn serve(&mut self, direction: i8, speed_x: f32, speed_y: f32, rng: &mut Rng) {
+        let direction = direction.signum() as f32;
+        // A little random vertical speed, so no two serves are quite alike
+        let jitter = rng.range(0, 201) as f32 / 100.0 - 1.0; // -1.0 to 1.0
+        self.velocity_x = direction * speed_x;
+        self.velocity_y = direction * speed_y + jitter * SERVE_JITTER_Y;
+        self.spin = 0.0;
+        self.speed = speed_x; // paddle hits build on the horizontal speed
+    }
+
+    /// Puts the ball back at (`x`, `y`) with a new velocity and no spin, e.g. after a point is
+    /// scored.
+    pub fn reset(&mut self, x: f32, y: f32, velocity_x: f32, velocity_y: f32) {
+        *self = Self::new(x, y, velocity_x, velocity_y);
+    }
+
+    /// Whether the ball, `size` × `size` pixels, overlaps the `paddle_w` × `paddle_h` paddle
+    /// with its top-left corner at (`paddle_x`, `paddle_y`). Touching edges do not count as
+    /// overlapping.
+    pub fn intersects_paddle(
+        &self,
+        size: f32,
+        paddle_x: f32,
+        paddle_y: f32,
+        paddle_w: f32,
+        paddle_h: f32,
+    ) -> bool {
+        self.x < paddle_x + paddle_w
+            && self.x + size > paddle_x
+            && self.y < paddle_y + paddle_h
+            && self.y + size > paddle_y
+    }
+
+    /// Reverses the horizontal direction, as when hitting a paddle.
+    pub fn bounce_x(&mut self) {
+        self.velocity_x = -self.velocity_x;
+    }
+
+    /// Reverses the vertical direction, as when hitting the top or bottom edge. The wall takes
+    /// any spin off the ball.
+    pub fn bounce_y(&mut self) {
+        self.velocity_y = -self.velocity_y;
+        self.spin = 0.0;
+    }
+
+    /// Left edge of the ball, rounded to the nearest pixel.
+    pub fn pixel_x(&self) -> usize {
+        (self.x + 0.5) as usize
+    }
+
+    /// Top edge of the ball, rounded to the nearest pixel.
+    pub fn pixel_y(&self) -> usize {
+        (self.y + 0.5) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 8.0;
+
+    /// Whether a ball at (`x`, `y`) overlaps a 10 × 60 paddle at (10, 100).
+    fn hits_paddle(x: f32, y: f32) -> bool {
+        Ball::new(x, y, 0.0, 0.0).intersects_paddle(SIZE, 10.0, 100.0, 10.0, 60.0)
+    }
+
+    #[test]
+    fn ball_grazing_a_paddle_corner_intersects_it() {
+        assert!(hits_paddle(19.5, 92.5)); // top right corner
+        assert!(hits_paddle(2.5, 159.5)); // bottom left corner
+    }
+
+    #[test]
+    fn ball_touching_a_paddle_corner_does_not_intersect_it() {
+        assert!(!hits_paddle(20.0, 92.0));
+        assert!(!hits_paddle(2.0, 160.0));
+    }
+
+    #[test]
+    fn ball_inside_a_paddle_intersects_it() {
+        assert!(hits_paddle(11.0, 120.0));
+    }
+
+    #[test]
+    fn paddle_bounce_reverses_only_horizontal_speed() {
+        let mut ball = Ball::new(0.0, 0.0, 3.0, 2.0);
+        ball.spin = 0.5;
+        ball.bounce_x();
+        assert_eq!(
+            (ball.velocity_x, ball.velocity_y, ball.spin),
+            (-3.0, 2.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn wall_bounce_reverses_vertical_speed_and_takes_the_spin() {
+        let mut ball = Ball::new(0.0, 0.0, 3.0, 2.0);
+        ball.spin = 0.5;
+        ball.bounce_y();
+        assert_eq!(
+            (ball.velocity_x, ball.velocity_y, ball.spin),
+            (3.0, -2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn positive_spin_bends_the_path_downward() {
+        let mut straight = Ball::new(0.0, 100.0, 4.0, 0.0);
+        let mut curved = Ball::new(0.0, 100.0, 4.0, 0.0);
+        curved.spin = 0.1;
+        let mut last_drop = 0.0;
+        for _ in 0..20 {
+            straight.update(1.0);
+            curved.update(1.0);
+            let drop = curved.y - straight.y;
+            assert!(
+                drop > last_drop,
+                "the curve should fall further behind every tick"
+            );
+            last_drop = drop;
+        }
+        assert_eq!(curved.x, straight.x);
+        assert_eq!(straight.y, 100.0);
+    }
+
+    #[test]
+    fn serve_heads_toward_the_given_side_at_the_given_speed() {
+        let mut rng = Rng::new(7);
+        for direction in [-1, 1] {
+            let mut ball = Ball::new(320.0, 240.0, 0.0, 0.0);
+            ball.spin = 0.3;
+            ball.serve(direction, 5.0, 2.0, &mut rng);
+            assert_eq!(ball.velocity_x, direction as f32 * 5.0);
+            let expected_y = direction as f32 * 2.0;
+            assert!((ball.velocity_y - expected_y).abs() <= SERVE_JITTER_Y);
+            assert_eq!((ball.speed, ball.spin), (5.0, 0.0));
+        }
+    }
+}
@@ -1,18 +1,23 @@
 use bootloader_api::info::MemoryRegionKind::Usable;
 use bootloader_api::info::MemoryRegions;
+use core::ops::Range;
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB};
 use x86_64::{PhysAddr, VirtAddr};
 
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryRegions,
+    heap: Range<u64>, // physical addresses given to the kernel heap
     next: usize,
 }
 
 impl BootInfoFrameAllocator {
-    pub fn new(memory_map: &'static MemoryRegions) -> Self {
+    /// Hands out the usable frames of `memory_map`, except those in `heap`, the physical memory
+    /// the kernel heap was given.
+    pub fn new(memory_map: &'static MemoryRegions, heap: Range<u64>) -> Self {
         BootInfoFrameAllocator {
             memory_map,
+            heap,
             next: 0,
         }
     }
@@ -22,6 +27,8 @@ impl BootInfoFrameAllocator {
         let usable_regions = regions.filter(|region| region.kind == Usable);
         let address_ranges = usable_regions.map(|region| region.start..region.end);
         let frame_addresses = address_ranges.flat_map(|region| region.step_by(4096));
+        let heap = self.heap.clone();
+        let frame_addresses = frame_addresses.filter(move |address| !heap.contains(address));
 
         frame_addresses.map(|address| PhysFrame::containing_address(PhysAddr::new(address)))
     }
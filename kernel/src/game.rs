@@ -0,0 +1,490 @@
+use crate::ball::Ball;
+use crate::{MAX_BALL_SPEED_Y, MAX_BALLS, PADDLE_HEIGHT, PADDLE_WIDTH, Side};
+
+/// A paddle as the ball physics sees it.
+#[derive(Debug, Clone, Copy)]
+pub struct Paddle {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub spin: f32, // given to a ball this paddle hits, see `Ball::spin`
+}
+
+/// Everything about the court that stays put while the balls move.
+#[derive(Debug, Clone, Copy)]
+pub struct Court {
+    pub width: f32,
+    pub height: f32,
+    pub border: f32, // the balls bounce off its inside at the top and bottom
+    pub ball_size: f32,
+    pub max_speed: f32,  // speed paddle hits stop speeding a ball up at
+    pub time_scale: f32, // frames the balls move per step, below 1 for slow motion
+    pub gravity: f32,    // added to every ball's vertical speed each frame, 0 for none
+    pub left: Paddle,
+    pub right: Paddle,
+}
+
+/// A power-up waiting on the court to be hit.
+pub struct PowerUp {
+    pub x: usize,
+    pub y: usize,
+    pub frames_left: u32, // ball frames until it disappears uncollected
+}
+
+/// How a player's paddle is sized and moving during a match.
+#[derive(Debug, Clone, Copy)]
+pub struct PaddleState {
+    pub width: usize,
+    pub height: usize,      // grown while a power-up lasts
+    pub growth_frames: u32, // ball frames left with a grown paddle
+    // Direction (-1 up, 1 down) the paddle is moving in, and for how many more ticks. Keyboards
+    // only report presses (and auto-repeat while held), so movement decays unless another press
+    // arrives.
+    pub direction: i32,
+    pub hold: u32,
+    pub moving_for: u32, // ticks it has kept moving the same way; it speeds up the longer this is
+    pub ai_target: usize, // where the computer is moving it, refreshed every `ai_reaction_ticks`
+    pub ai_frozen: u32,  // ticks the computer leaves it standing after a mistake
+}
+
+impl PaddleState {
+    /// A `width` × `height` paddle standing still, which the computer keeps at `ai_target`.
+    pub const fn new(width: usize, height: usize, ai_target: usize) -> Self {
+        Self {
+            width,
+            height,
+            growth_frames: 0,
+            direction: 0,
+            hold: 0,
+            moving_for: 0,
+            ai_target,
+            ai_frozen: 0,
+        }
+    }
+}
+
+/// Everything that changes while a match is played. Settings and the sizes `layout` works out
+/// are kept apart, in `main.rs`, as they outlive a match.
+pub struct GameState {
+    pub phase: i32, // one of the `STATE_` constants in `main.rs`
+    pub balls: [Ball; MAX_BALLS],
+    pub active_balls: usize, // only the first `active_balls` balls are in play
+    pub paddle_left: usize,  // top edge of the left paddle
+    pub paddle_right: usize, // top edge of the right paddle
+    pub left_score: i32,     // in the current set
+    pub right_score: i32,
+    pub left_sets: i32,
+    pub right_sets: i32,
+    pub set_banner: bool, // show which set is starting during the serve countdown
+    pub rally: i32,       // paddle hits since the last point
+    pub last_hitter: Option<Side>, // paddle that last hit a ball
+    pub flash: Option<(Side, u32)>, // half of the court lit up after a point, and ticks left
+    pub left_paddle_state: PaddleState,
+    pub right_paddle_state: PaddleState,
+    // A square on the court that grows the paddle of whoever last hit the ball through it
+    pub power_up: Option<PowerUp>,
+    pub power_up_delay: u32,  // ball frames until the next power-up appears
+    pub serve_countdown: u32, // ball frames left before the ball is released
+    pub started_at: u64,      // `TICK_COUNT` when the match began
+    pub ended_at: u64,        // `TICK_COUNT` when it was won
+}
+
+impl GameState {
+    pub const fn new(phase: i32) -> Self {
+        Self {
+            phase,
+            balls: [const { Ball::new(0.0, 0.0, 0.0, 0.0) }; MAX_BALLS],
+            active_balls: 0,
+            paddle_left: 0,
+            paddle_right: 0,
+            left_score: 0,
+            right_score: 0,
+            left_sets: 0,
+            right_sets: 0,
+            set_banner: false,
+            rally: 0,
+            last_hitter: None,
+            flash: None,
+            left_paddle_state: PaddleState::new(PADDLE_WIDTH, PADDLE_HEIGHT, 0),
+            right_paddle_state: PaddleState::new(PADDLE_WIDTH, PADDLE_HEIGHT, 0),
+            power_up: None,
+            power_up_delay: 0,
+            serve_countdown: 0,
+            started_at: 0,
+            ended_at: 0,
+        }
+    }
+
+    /// The balls currently in play.
+    pub fn balls(&mut self) -> &mut [Ball] {
+        &mut self.balls[..self.active_balls]
+    }
+
+    /// Top edge of the paddle on `side`.
+    pub fn paddle(&mut self, side: Side) -> &mut usize {
+        match side {
+            Side::Left => &mut self.paddle_left,
+            Side::Right => &mut self.paddle_right,
+        }
+    }
+
+    /// Size and movement of the paddle on `side`.
+    pub fn paddle_state(&mut self, side: Side) -> &mut PaddleState {
+        match side {
+            Side::Left => &mut self.left_paddle_state,
+            Side::Right => &mut self.right_paddle_state,
+        }
+    }
+
+    /// Points scored by `side` in the current set.
+    pub fn score(&mut self, side: Side) -> &mut i32 {
+        match side {
+            Side::Left => &mut self.left_score,
+            Side::Right => &mut self.right_score,
+        }
+    }
+
+    /// Sets won by `side` in this match.
+    pub fn sets(&mut self, side: Side) -> &mut i32 {
+        match side {
+            Side::Left => &mut self.left_sets,
+            Side::Right => &mut self.right_sets,
+        }
+    }
+}
+
+/// Something a ball did during `step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Hit(Side),   // bounced off the paddle on this side
+    Score(Side), // left the court, a point for this side
+    Bounce,      // bounced off the top or bottom border, and nothing else happened
+}
+
+/// What happened during one `step`, at most one event per ball, in the order the balls moved.
+pub type Outcome = [Option<Event>; MAX_BALLS];
+
+/// Moves every ball in play by `court.time_scale` frames, bouncing it off the top and bottom
+/// of `court` and the paddles. Balls that leave the court are taken out of play, which may
+/// change the order of the remaining ones. Reads no globals and draws nothing, so a match can be
+/// played out by calling `step` in a loop.
+pub fn step(state: &mut GameState, court: &Court) -> Outcome {
+    let mut outcome = [None; MAX_BALLS];
+    let mut events = outcome.iter_mut();
+    let mut index = 0;
+    while index < state.active_balls {
+        let event = step_ball(court, &mut state.balls[index]);
+        if let Some(Event::Score(_)) = event {
+            state.balls.swap(index, state.active_balls - 1);
+            state.active_balls -= 1;
+        } else {
+            index += 1;
+        }
+        if let (Some(event), Some(slot)) = (event, events.next()) {
+            *slot = Some(event);
+        }
+    }
+    outcome
+}
+
+/// Moves `ball` by `court.time_scale` frames. See `step`.
+fn step_ball(court: &Court, ball: &mut Ball) -> Option<Event> {
+    let from = (ball.x, ball.y);
+    ball.velocity_y += court.gravity * court.time_scale; // pulls like spin, but never wears off
+    ball.update(court.time_scale);
+    ball.velocity_y = ball.velocity_y.clamp(-MAX_BALL_SPEED_Y, MAX_BALL_SPEED_Y); // spin adds up
+    let size = court.ball_size;
+
+    // Bounce off the inside of the border
+    let top = court.border;
+    let bottom = court.height - court.border;
+    let bounced = ball.y < top || ball.y + size > bottom;
+    if ball.y < top {
+        ball.y = top;
+        ball.bounce_y(); // Bounce downward
+    } else if ball.y + size > bottom {
+        ball.y = bottom - size; // Clamp to bottom
+        ball.bounce_y(); // Bounce upward
+    }
+
+    // Both paddles are hit exactly at their face: the ball's left edge meets the left paddle at
+    // `left.x + left.width`, and its right edge meets the right paddle at `right.x`, which is
+    // where it is put back after a bounce. A ball fast enough to jump over a paddle in one frame
+    // still hits it if its path crossed the face, so paddles are checked before scoring.
+    let (left, right) = (&court.left, &court.right);
+    if ball.velocity_x > 0.0
+        && (ball.intersects_paddle(size, right.x, right.y, right.width, right.height)
+            || crossed_face(from, ball, size, size, right.x, right))
+    {
+        hit(court, ball, right);
+        // Push the ball out of the paddle so a slow ball can't hit it again next frame
+        ball.x = right.x - size;
+        return Some(Event::Hit(Side::Right));
+    }
+    if ball.velocity_x < 0.0
+        && (ball.intersects_paddle(size, left.x, left.y, left.width, left.height)
+            || crossed_face(from, ball, size, 0.0, left.x + left.width, left))
+    {
+        hit(court, ball, left);
+        ball.x = left.x + left.width; // Out of the paddle, like on the right
+        return Some(Event::Hit(Side::Left));
+    }
+
+    // Check for scoring conditions
+    if ball.x < 0.0 {
+        return Some(Event::Score(Side::Right));
+    } else if ball.x + size > court.width {
+        return Some(Event::Score(Side::Left));
+    }
+    bounced.then_some(Event::Bounce)
+}
+
+/// Whether `ball`, `size` pixels square, moved from `from` to where it is now through the face
+/// of `paddle` at `face_x`. `edge` is the offset from the ball's left side to the side leading
+/// into the face. The height at the crossing is interpolated along the ball's path.
+fn crossed_face(
+    from: (f32, f32),
+    ball: &Ball,
+    size: f32,
+    edge: f32,
+    face_x: f32,
+    paddle: &Paddle,
+) -> bool {
+    let (start, end) = (from.0 + edge, ball.x + edge);
+    if start == end || (start - face_x) * (end - face_x) > 0.0 {
+        return false; // both ends on the same side of the face
+    }
+    let y = from.1 + (ball.y - from.1) * (face_x - start) / (end - start);
+    y < paddle.y + paddle.height && y + size > paddle.y
+}
+
+/// Sends `ball` back from `paddle`, a little faster, at an angle depending on where it hit.
+/// The angle only turns the velocity: it is scaled back to the rally's `Ball::speed` however
+/// steep the hit, so hits near the paddle ends don't speed the ball up.
+fn hit(court: &Court, ball: &mut Ball, paddle: &Paddle) {
+    ball.speed = speed_up(ball.speed, court.max_speed);
+    ball.bounce_x();
+    let velocity_x = ball.velocity_x.signum() * ball.speed;
+    let velocity_y = deflect(ball.y + court.ball_size / 2.0, paddle);
+    let scale = ball.speed / length(velocity_x, velocity_y);
+    ball.velocity_x = velocity_x * scale;
+    ball.velocity_y = velocity_y * scale;
+    ball.spin = paddle.spin;
+}
+
+/// Adds 1 to a ball speed, up to `max_speed`.
+fn speed_up(speed: f32, max_speed: f32) -> f32 {
+    (speed + 1.0).min(max_speed)
+}
+
+/// Length of the vector (`x`, `y`), by a few Newton steps as `core` has no square root.
+/// `x` must not be 0.
+fn length(x: f32, y: f32) -> f32 {
+    let square = x * x + y * y;
+    let mut root = x.abs().max(y.abs()); // within a factor of 1.5 of the result
+    for _ in 0..4 {
+        root = (root + square / root) / 2.0;
+    }
+    root
+}
+
+/// Vertical speed of a ball whose center at `ball_center` hit `paddle`.
+/// Center hits go flat, hits near either end leave at up to `MAX_BALL_SPEED_Y`.
+fn deflect(ball_center: f32, paddle: &Paddle) -> f32 {
+    let offset = ball_center - (paddle.y + paddle.height / 2.0);
+    (offset * MAX_BALL_SPEED_Y / (paddle.height / 2.0)).clamp(-MAX_BALL_SPEED_Y, MAX_BALL_SPEED_Y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    const BALL_SIZE: f32 = 8.0;
+
+    fn paddle(x: f32, y: f32) -> Paddle {
+        Paddle {
+            x,
+            y,
+            width: 10.0,
+            height: 60.0,
+            spin: 0.0,
+        }
+    }
+
+    /// A 640 × 480 court with the paddles 10 pixels in from the sides, faces at x 20 and 620,
+    /// and their top edges at `paddle_y`.
+    fn court(paddle_y: f32) -> Court {
+        Court {
+            width: 640.0,
+            height: 480.0,
+            border: 2.0,
+            ball_size: BALL_SIZE,
+            max_speed: 12.0,
+            time_scale: 1.0,
+            gravity: 0.0,
+            left: paddle(10.0, paddle_y),
+            right: paddle(620.0, paddle_y),
+        }
+    }
+
+    /// A match with only `ball` in play.
+    fn playing(ball: Ball) -> GameState {
+        let mut state = GameState::new(0);
+        state.balls[0] = ball;
+        state.active_balls = 1;
+        state
+    }
+
+    /// Steps `state` `steps` times and returns every event, in order.
+    fn play(state: &mut GameState, court: &Court, steps: usize) -> Vec<Event> {
+        (0..steps)
+            .flat_map(|_| step(state, court).into_iter().flatten())
+            .collect()
+    }
+
+    #[test]
+    fn steep_ball_bounces_off_the_face_once() {
+        let court = court(200.0);
+        // Right at the left paddle's face, coming in almost straight down
+        let mut state = playing(Ball::new(21.0, 200.0, -0.5, 6.0));
+        let events = play(&mut state, &court, 10);
+        assert_eq!(events, [Event::Hit(Side::Left)]);
+        let ball = &state.balls[0];
+        assert!(ball.velocity_x > 0.0);
+        assert!(ball.x >= 20.0);
+    }
+
+    #[test]
+    fn large_ball_bounces_cleanly_off_both_paddles() {
+        let mut court = court(200.0);
+        court.ball_size = 24.0;
+        let mut state = playing(Ball::new(300.0, 218.0, 5.0, 0.0)); // level with the centers
+        let events = play(&mut state, &court, 70);
+        assert_eq!(events[0], Event::Hit(Side::Right));
+        let ball = &state.balls[0];
+        assert!(ball.velocity_x < 0.0);
+        let events = play(&mut state, &court, 200);
+        assert_eq!(events[0], Event::Hit(Side::Left));
+        assert!(!events.iter().any(|event| matches!(event, Event::Score(_))));
+    }
+
+    /// The event of moving `ball` one step on `court`, and where the ball ends up.
+    fn one_step(court: &Court, ball: Ball) -> (Option<Event>, f32) {
+        let mut state = playing(ball);
+        let event = step(&mut state, court)[0];
+        (event, state.balls[0].x)
+    }
+
+    #[test]
+    fn balls_hit_each_paddle_exactly_at_its_face() {
+        let court = court(200.0);
+        // The right paddle's face is at x 620, where the ball's right edge meets it
+        assert_eq!(
+            one_step(&court, Ball::new(611.0, 220.0, 0.5, 0.0)),
+            (None, 611.5)
+        );
+        let (event, x) = one_step(&court, Ball::new(611.5, 220.0, 0.5, 0.0));
+        assert_eq!(
+            (event, x + BALL_SIZE),
+            (Some(Event::Hit(Side::Right)), 620.0)
+        );
+        // The left paddle's face is at x 20, where the ball's left edge meets it
+        assert_eq!(
+            one_step(&court, Ball::new(21.0, 220.0, -0.5, 0.0)),
+            (None, 20.5)
+        );
+        let hit = one_step(&court, Ball::new(20.5, 220.0, -0.5, 0.0));
+        assert_eq!(hit, (Some(Event::Hit(Side::Left)), 20.0));
+    }
+
+    #[test]
+    fn long_match_keeps_the_ball_in_the_court_and_scores_for_the_right_side() {
+        let mut court = court(210.0);
+        let mut rng = Rng::new(42);
+        let mut state = playing(Ball::new(316.0, 236.0, 0.0, 0.0));
+        state.balls[0].serve(1, 4.0, 2.0, &mut rng);
+        let (mut hits, mut aim) = (0, 0.0);
+        for _ in 0..20_000 {
+            // Both paddles chase the ball, too slowly to always get there, and hit it off center
+            let target = state.balls[0].y + BALL_SIZE / 2.0 - 30.0 + aim;
+            for paddle in [&mut court.left, &mut court.right] {
+                paddle.y += (target - paddle.y).clamp(-3.0, 3.0);
+            }
+            let before = state.balls[0].x;
+            match step(&mut state, &court)[0] {
+                Some(Event::Score(side)) => {
+                    // Out on the far side from the scorer, having been moving that way
+                    let out_left = state.balls[0].x < 0.0;
+                    assert_eq!(side, if out_left { Side::Right } else { Side::Left });
+                    assert_eq!(out_left, state.balls[0].x < before);
+                    *state.score(side) += 1;
+                    assert_eq!(state.active_balls, 0);
+                    state.balls[0].reset(316.0, 236.0, 0.0, 0.0);
+                    state.balls[0].serve(if out_left { 1 } else { -1 }, 4.0, 2.0, &mut rng);
+                    state.active_balls = 1;
+                }
+                Some(Event::Hit(_)) => {
+                    hits += 1;
+                    aim = rng.range(0, 51) as f32 - 25.0;
+                }
+                _ => {}
+            }
+            let ball = &state.balls[0];
+            assert!(ball.y >= court.border && ball.y + BALL_SIZE <= court.height - court.border);
+            assert!(
+                ball.x + BALL_SIZE >= 0.0 && ball.x <= court.width,
+                "x {}",
+                ball.x
+            );
+        }
+        assert!(hits > 0);
+        assert!(state.left_score > 0 && state.right_score > 0);
+    }
+
+    #[test]
+    fn ball_too_fast_to_land_on_a_paddle_still_hits_it() {
+        let mut court = court(200.0);
+        court.max_speed = 40.0;
+        // 40 pixels a frame jumps from in front of either paddle to behind it
+        let mut state = playing(Ball::new(590.0, 220.0, 40.0, 0.0));
+        assert_eq!(play(&mut state, &court, 1), [Event::Hit(Side::Right)]);
+        assert_eq!(state.balls[0].x + BALL_SIZE, 620.0);
+        let mut state = playing(Ball::new(30.0, 220.0, -40.0, 0.0));
+        assert_eq!(play(&mut state, &court, 1), [Event::Hit(Side::Left)]);
+        assert_eq!(state.balls[0].x, 20.0);
+    }
+
+    #[test]
+    fn gravity_bends_a_level_serve_downward() {
+        let mut court = court(200.0);
+        court.gravity = 0.15;
+        let mut state = playing(Ball::new(316.0, 100.0, 4.0, 0.0));
+        let mut last_y = 100.0;
+        for _ in 0..20 {
+            assert_eq!(step(&mut state, &court), [None; MAX_BALLS]);
+            let ball = &state.balls[0];
+            assert!(ball.y > last_y, "still falling at y {}", ball.y);
+            last_y = ball.y;
+        }
+        assert!(state.balls[0].velocity_y > 2.9); // 20 frames of 0.15
+    }
+
+    #[test]
+    fn paddle_hits_anywhere_leave_at_the_rally_speed() {
+        let court = court(200.0);
+        // From the top corner of the right paddle to its bottom corner
+        for y in (193..=259).step_by(6) {
+            let mut state = playing(Ball::new(608.0, y as f32, 5.0, 1.0));
+            assert_eq!(play(&mut state, &court, 1), [Event::Hit(Side::Right)]);
+            let ball = &state.balls[0];
+            let speed = length(ball.velocity_x, ball.velocity_y);
+            assert!(
+                (speed - 6.0).abs() < 0.01,
+                "speed {speed} after a hit at y {y}"
+            );
+            assert!(ball.velocity_x < 0.0);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::Side;
+use kernel::RacyCell;
+
+/// Paddle key presses that can wait for the next tick; further presses are dropped
+const CAPACITY: usize = 8;
+
+/// Presses handed on per call of `pop_for_tick`, so a burst is spread over a few ticks
+const PRESSES_PER_TICK: usize = 2;
+
+/// A paddle key press: the paddle and its direction, -1 up or 1 down
+pub type PaddlePress = (Side, i32);
+
+/// Fixed-size ring buffer of paddle key presses, oldest first
+struct PressQueue {
+    presses: [Option<PaddlePress>; CAPACITY],
+    head: usize, // slot of the oldest press
+    len: usize,
+}
+
+static QUEUE: RacyCell<PressQueue> = RacyCell::new(PressQueue {
+    presses: [None; CAPACITY],
+    head: 0,
+    len: 0,
+});
+
+/// Queues a paddle key press for the next ticks. Called from the keyboard handler, so
+/// presses arriving between two ticks are applied one after the other instead of at once.
+/// Returns `false` if the queue is full and the press was dropped.
+pub fn push(press: PaddlePress) -> bool {
+    let queue = unsafe { QUEUE.get_mut() };
+    if queue.len == CAPACITY {
+        return false;
+    }
+    queue.presses[(queue.head + queue.len) % CAPACITY] = Some(press);
+    queue.len += 1;
+    true
+}
+
+/// Takes up to `PRESSES_PER_TICK` of the oldest queued presses. Call once per tick.
+pub fn pop_for_tick() -> impl Iterator<Item = PaddlePress> {
+    let queue = unsafe { QUEUE.get_mut() };
+    let count = queue.len.min(PRESSES_PER_TICK);
+    let mut presses = [None; PRESSES_PER_TICK];
+    for press in presses.iter_mut().take(count) {
+        *press = queue.presses[queue.head].take();
+        queue.head = (queue.head + 1) % CAPACITY;
+    }
+    queue.len -= count;
+    presses.into_iter().flatten()
+}
+
+/// Drops every queued press, e.g. when the paddles are put back at the center.
+pub fn clear() {
+    let queue = unsafe { QUEUE.get_mut() };
+    *queue = PressQueue {
+        presses: [None; CAPACITY],
+        head: 0,
+        len: 0,
+    };
+}
@@ -294,6 +294,14 @@ fn end_interrupt() {
     unsafe { binding.address.offset(APICOffset::Eoi as isize / 4).write_volatile(0); }
 }
 
+/// Reads the current count of the APIC timer, which runs down from the initial count between
+/// two timer interrupts.
+#[allow(dead_code, reason = "only the kernel binary, which builds this module too, seeds from it")]
+pub fn timer_count() -> u32 {
+    let binding = LAPIC_ADDR.lock();
+    unsafe { binding.address.offset(APICOffset::Tccr as isize / 4).read_volatile() }
+}
+
 /// Initializes the interrupt table with the given interrupt handlers.
 pub fn init_idt(handlers: HandlerTable, lapic_pointer: *mut u32) {
     LAPIC_ADDR.lock().address = lapic_pointer;
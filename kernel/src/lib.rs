@@ -1,21 +1,55 @@
 // Original code from rust-osdev/bootloader crate https://github.com/rust-osdev/bootloader
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(abi_x86_interrupt)]
 
 use core::cell::UnsafeCell;
-use core::panic::PanicInfo;
-use core::fmt::Write;
-use uart_16550::SerialPort;
+#[cfg(target_os = "none")]
+pub use uart_16550::SerialPort;
 use pc_keyboard::DecodedKey;
 
 mod interrupts;
 
 extern crate alloc;
 
-pub fn serial() -> SerialPort {
-    let mut port = unsafe { SerialPort::new(0x3F8) };
-    port.init();
-    port
+static SERIAL: RacyCell<Option<SerialPort>> = RacyCell::new(None);
+
+/// The first serial port (COM1), set up on first use and shared from then on. Setting it up
+/// resets the UART and drops the bytes waiting in its receive FIFO, such as the remote
+/// player's commands, so that happens only once.
+pub fn serial() -> &'static mut SerialPort {
+    let port = unsafe { SERIAL.get_mut() };
+    port.get_or_insert_with(|| {
+        let mut port = unsafe { SerialPort::new(0x3F8) };
+        port.init();
+        port
+    })
+}
+
+/// Stands in for the UART in host builds for `cargo test`, where a user program may not touch
+/// I/O ports: whatever is written to it is dropped and nothing is ever received.
+#[cfg(not(target_os = "none"))]
+pub struct SerialPort;
+
+#[cfg(not(target_os = "none"))]
+impl SerialPort {
+    /// ## Safety
+    /// Always safe; `unsafe` only to match the real port's constructor.
+    pub unsafe fn new(_base: u16) -> Self {
+        SerialPort
+    }
+
+    pub fn init(&mut self) {}
+
+    pub fn try_receive(&mut self) -> Result<u8, ()> {
+        Err(())
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl core::fmt::Write for SerialPort {
+    fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+        Ok(())
+    }
 }
 
 /// Table of interrupt handlers. This struct uses the
@@ -100,8 +134,10 @@ pub fn hlt_loop() -> ! {
     }
 }
 
+#[cfg(target_os = "none")] // host builds for `cargo test` use std's
 #[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
     let _ = writeln!(serial(), "PANIC: {info}");
     hlt_loop();
 }
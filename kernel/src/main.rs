@@ -1,43 +1,223 @@
 #![feature(sync_unsafe_cell)]
 #![feature(abi_x86_interrupt)]
-#![no_std] // don't link the Rust standard library
-#![no_main] // disable all Rust-level entry points
+#![feature(alloc_error_handler)]
+#![cfg_attr(test, feature(allocator_api))] // lets tests allocate `Box`es from a test heap
+#![cfg_attr(test, feature(test))] // benchmarks, run with `cargo bench` in this directory
+#![cfg_attr(not(test), no_std)] // don't link the Rust standard library
+#![cfg_attr(not(test), no_main)] // disable all Rust-level entry points
+// Host tests (`cargo test` in this directory) have no `kernel_main`, so most code looks unused
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 
 extern crate alloc;
 
 mod allocator;
+mod ball;
 mod frame_allocator;
+mod game;
 mod gdt;
+mod input;
 mod interrupts;
+mod remote;
+mod replay;
+mod rng;
+mod rtc;
 mod screen;
+mod screenshot;
+mod sound;
 
+use crate::ball::Ball;
 use crate::frame_allocator::BootInfoFrameAllocator;
-use crate::screen::{Writer, screenwriter};
+use crate::game::{Court, Event, GameState, PaddleState, PowerUp};
+use crate::rng::{Rng, rng};
+use crate::screen::{
+    BORDER_THICKNESS, BallShape, Color, LINE_HEIGHT, ScreenWriter, THEMES, Writer, screenwriter,
+};
 use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use bootloader_api::config::Mapping::Dynamic;
 use bootloader_api::info::MemoryRegionKind;
 use bootloader_api::{BootInfo, BootloaderConfig, entry_point};
+use core::fmt;
 use core::fmt::Write;
+use core::ops::RangeInclusive;
 use core::slice;
-use core::sync::atomic::{AtomicI32, Ordering};
-use kernel::{HandlerTable, serial};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use kernel::{HandlerTable, RacyCell, serial};
 use pc_keyboard::DecodedKey;
 use pc_keyboard::KeyCode;
 use x86_64::VirtAddr;
 use x86_64::registers::control::Cr3;
 
-pub static mut PADDLE_LEFT: usize = 100;
-pub static mut PADDLE_RIGHT: usize = 100;
-pub const PADDLE_WIDTH: usize = 10;
-pub const PADDLE_HEIGHT: usize = 60;
-pub static mut BALL_X: usize = 200;
-pub static mut BALL_Y: usize = 150;
-pub const BALL_SIZE: usize = 8;
-pub static mut BALL_SPEED_X: isize = 5;
-pub static mut BALL_SPEED_Y: isize = 3;
-static LEFT_SCORE: AtomicI32 = AtomicI32::new(0);
-static RIGHT_SCORE: AtomicI32 = AtomicI32::new(0);
-static GAME_STATE: AtomicI32 = AtomicI32::new(0); // 0: ongoing, 1: ended
+/// The match being played, see `game_state`
+static GAME: RacyCell<GameState> = RacyCell::new(GameState::new(STATE_MENU));
+pub const PADDLE_WIDTH: usize = 10; // default width of each paddle, see `LAYOUT_HEIGHT`
+pub const PADDLE_HEIGHT: usize = 60; // unless grown by a power-up
+pub const PADDLE_MARGIN: usize = 10; // gap between each paddle and its edge of the screen
+pub const BALL_SIZE: usize = 8; // default width and height of each ball
+/// Screen height the sizes of game objects are given for. `layout` scales them to the actual
+/// screen, so the game keeps its proportions at any resolution.
+pub const LAYOUT_HEIGHT: usize = 720;
+// Sizes worked out by `layout`
+static LAYOUT_PADDLE_WIDTH: AtomicUsize = AtomicUsize::new(PADDLE_WIDTH);
+static LAYOUT_PADDLE_HEIGHT: AtomicUsize = AtomicUsize::new(PADDLE_HEIGHT);
+static LAYOUT_POWER_UP_GROWTH: AtomicUsize = AtomicUsize::new(POWER_UP_GROWTH);
+static LAYOUT_SCORE_SIZE: AtomicUsize = AtomicUsize::new(SCORE_SIZE);
+static CURRENT_BALL_SIZE: AtomicUsize = AtomicUsize::new(BALL_SIZE); // see `set_ball_size`
+pub const PADDLE_SPIN: f32 = 0.05; // spin given by a moving paddle, see `Ball::spin`
+pub const MAX_BALL_SPEED_Y: f32 = 8.0; // vertical speed after hitting a paddle's very edge
+pub const MAX_BALLS: usize = 4;
+static MULTIBALL: AtomicBool = AtomicBool::new(false); // serve two balls at once
+static THEME_INDEX: AtomicUsize = AtomicUsize::new(0); // index into `screen::THEMES`
+// Looks the balls can be given with 'u': name, shape, and whether they take the paddle color
+const BALL_STYLES: [(&str, BallShape, bool); 3] = [
+    ("Round", BallShape::Round, false),
+    ("Square", BallShape::Square, false),
+    ("Puck", BallShape::Round, true),
+];
+static BALL_STYLE: AtomicUsize = AtomicUsize::new(0); // index into BALL_STYLES
+// Colors the balls can be given with 'c' to stand out better, over the theme's and the style's
+const BALL_COLORS: [(&str, Option<Color>); 6] = [
+    ("Theme", None),
+    ("White", Some((255, 255, 255))),
+    ("Yellow", Some((255, 255, 0))),
+    ("Cyan", Some((0, 255, 255))),
+    ("Magenta", Some((255, 0, 255))),
+    ("Orange", Some((255, 128, 0))),
+];
+static BALL_COLOR: AtomicUsize = AtomicUsize::new(0); // index into BALL_COLORS
+static HIGH_CONTRAST_TEXT: AtomicBool = AtomicBool::new(false); // see `set_high_contrast_text`
+const SCORE_Y: usize = 10; // top of the scores, or of the sets won above them
+const SETS_GAP: usize = 6; // between the sets won and the scores
+const SCORE_SIZE: usize = 30;
+// Power-ups, see `GameState::power_up`
+const POWER_UP_SIZE: usize = 16;
+pub const POWER_UP_GROWTH: usize = 30; // extra paddle height while a power-up lasts
+pub const POWER_UP_SECONDS: u32 = 10; // how long a collected power-up lasts
+pub const POWER_UP_LIFETIME_SECONDS: u32 = 8; // how long an uncollected power-up stays
+pub const POWER_UP_MIN_DELAY_SECONDS: u32 = 5;
+pub const POWER_UP_MAX_DELAY_SECONDS: u32 = 15;
+const RALLY_SIZE: usize = 12;
+static HIGH_SCORE: AtomicI32 = AtomicI32::new(0); // best score of any player since boot
+// Phases of a match, see `GameState::phase`
+const STATE_PLAYING: i32 = 0;
+const STATE_ENDED: i32 = 1;
+const STATE_PAUSED: i32 = 2;
+const STATE_MENU: i32 = 3;
+const PAUSED_TEXT: &str = "PAUSED";
+static END_SCREEN_DRAWN: AtomicBool = AtomicBool::new(false); // game-over screen is up to date
+const FINAL_SCORE_SIZE: usize = 60;
+// Attract mode: the computer plays itself after the menu has been left alone for a while
+static DEMO_MODE: AtomicBool = AtomicBool::new(false);
+static MENU_IDLE_TICKS: AtomicU32 = AtomicU32::new(0);
+pub const DEMO_IDLE_SECONDS: u32 = 10;
+const DEMO_TEXT: &str = "Press SPACE to play";
+/// Rate `tick()` is run at, whatever the rate of the APIC timer, see `timer`
+pub const TICKS_PER_SECOND: u32 = 60;
+// Timer interrupts per second, measured against the real-time clock. The APIC timer's rate
+// depends on the hardware, so it is assumed to be `TICKS_PER_SECOND` until measured.
+static TIMER_RATE: AtomicU32 = AtomicU32::new(TICKS_PER_SECOND);
+static TIMER_INTERRUPTS: AtomicU32 = AtomicU32::new(0); // since the last RTC second
+static TIMER_RTC_SECOND: AtomicU32 = AtomicU32::new(u32::MAX); // none seen yet
+static TICK_CREDIT: AtomicU32 = AtomicU32::new(0); // remainder carried over between interrupts
+const MAX_TICKS_PER_INTERRUPT: u32 = 4; // catching up further would only lag more
+pub const SERVE_COUNTDOWN_SECONDS: u32 = 3;
+const COUNTDOWN_SIZE: usize = 40;
+// The ball only moves on every SPEED_DIVIDER-th tick, see `ball_frame_due`
+static SPEED_DIVIDER: AtomicU32 = AtomicU32::new(SPEED_NORMAL);
+static TICKS_SINCE_BALL_FRAME: AtomicU32 = AtomicU32::new(0);
+pub const SPEED_EASY: u32 = 3;
+pub const SPEED_NORMAL: u32 = 2;
+pub const SPEED_FAST: u32 = 1;
+static TICK_COUNT: AtomicU64 = AtomicU64::new(0); // ticks since boot
+static LOG_EVENTS: AtomicBool = AtomicBool::new(true); // toggled with 'l', see `log_event`
+// Events logged while a screenshot is being sent, written after it so they don't split its rows
+static HELD_EVENTS: RacyCell<Vec<String>> = RacyCell::new(Vec::new());
+static SHOW_FPS: AtomicBool = AtomicBool::new(false); // toggled with 'f'
+static DEBUG: AtomicBool = AtomicBool::new(false); // toggled with F9, see `set_debug_score`
+static CALIBRATING: AtomicBool = AtomicBool::new(false); // F1 on the menu, see `draw_calibration`
+const SHIFTED_DIGITS: &str = "!@#$%^&*("; // Shift+'1' to Shift+'9' on a US keyboard
+static LAST_RTC_SECOND: AtomicU32 = AtomicU32::new(0);
+static TICKS_AT_LAST_SECOND: AtomicU64 = AtomicU64::new(0);
+const FPS_TEXT_SAMPLE: &str = "FPS: 00000"; // widest text the counter is expected to show
+const PADDLE_HIT_BEEP: (u32, u32) = (880, 4); // (Hz, ticks)
+const WALL_BOUNCE_BEEP: (u32, u32) = (440, 3);
+const SCORE_BEEP: (u32, u32) = (220, 12);
+static WIN_SCORE: AtomicI32 = AtomicI32::new(3); // points needed to win a set
+static BEST_OF: AtomicI32 = AtomicI32::new(1); // sets in a match at most, 1 for a single game
+const BEST_OF_CHOICES: [i32; 4] = [1, 3, 5, 7]; // cycled with 'e'
+static WIN_BY_TWO: AtomicBool = AtomicBool::new(false); // a winner also has to lead by two points
+const DEUCE_TEXT_SAMPLE: &str = "Advantage right"; // widest text `draw_deuce` shows
+static SLOW_MATCH_POINT: AtomicBool = AtomicBool::new(true); // slow the ball on match point
+const MATCH_POINT_TIME_SCALE: f32 = 0.5; // ball speed on match point, 1.0 being normal
+const MATCH_POINT_TEXT: &str = "MATCH POINT";
+static SCORE_FLASH: AtomicBool = AtomicBool::new(true); // flash the conceding half on a point
+const FLASH_TICKS: u32 = 6;
+// Downward pull on the balls, in pixels per ball frame added to their vertical speed each frame.
+// Cycled with 'g'; anything but "Off" bends straight shots into arcs.
+const GRAVITY_LEVELS: [(&str, f32); 4] =
+    [("Off", 0.0), ("Subtle", 0.05), ("Strong", 0.15), ("Extreme", 0.4)];
+static GRAVITY: AtomicUsize = AtomicUsize::new(0); // index into GRAVITY_LEVELS
+pub const MIN_WIN_SCORE: i32 = 1;
+pub const MAX_WIN_SCORE: i32 = 21;
+static SINGLE_PLAYER: AtomicBool = AtomicBool::new(false); // right paddle driven by the computer
+static REMOTE_PLAYER: AtomicBool = AtomicBool::new(false); // right paddle driven over serial
+static DIFFICULTY: AtomicUsize = AtomicUsize::new(1); // index into DIFFICULTIES, Normal
+const AI_FREEZE_TICKS: u32 = 15;
+pub const PADDLE_HOLD_TICKS: u32 = 8; // how long one key event keeps a paddle moving
+// Pixels per tick a paddle moves: slow at first so taps give fine control, then one more pixel
+// every `PADDLE_ACCELERATION_TICKS` ticks up to the top speed
+pub const PADDLE_MIN_STEP: isize = 2;
+pub const PADDLE_MAX_STEP: isize = 12;
+pub const PADDLE_ACCELERATION_TICKS: u32 = 4;
+static CONTROLS: AtomicUsize = AtomicUsize::new(0); // index into CONTROL_SCHEMES
+
+/// Keys moving the paddles. Letters are given in lower case and match either case.
+struct Controls {
+    name: &'static str,
+    left_up: DecodedKey,
+    left_down: DecodedKey,
+    right_up: DecodedKey,
+    right_down: DecodedKey,
+}
+
+impl Controls {
+    /// Whether `key`, in lower case, moves a paddle.
+    fn uses(&self, key: DecodedKey) -> bool {
+        [self.left_up, self.left_down, self.right_up, self.right_down].contains(&key)
+    }
+}
+
+const CONTROL_SCHEMES: [Controls; 3] = [
+    Controls {
+        name: "W/S left, arrows right",
+        left_up: DecodedKey::Unicode('w'),
+        left_down: DecodedKey::Unicode('s'),
+        right_up: DecodedKey::RawKey(KeyCode::ArrowUp),
+        right_down: DecodedKey::RawKey(KeyCode::ArrowDown),
+    },
+    Controls {
+        name: "arrows left, W/S right",
+        left_up: DecodedKey::RawKey(KeyCode::ArrowUp),
+        left_down: DecodedKey::RawKey(KeyCode::ArrowDown),
+        right_up: DecodedKey::Unicode('w'),
+        right_down: DecodedKey::Unicode('s'),
+    },
+    // Letters only, so two players can share one side of the keyboard
+    Controls {
+        name: "W/S left, O/L right",
+        left_up: DecodedKey::Unicode('w'),
+        left_down: DecodedKey::Unicode('s'),
+        right_up: DecodedKey::Unicode('o'),
+        right_down: DecodedKey::Unicode('l'),
+    },
+];
+
+/// The control scheme picked on the menu.
+fn controls() -> &'static Controls {
+    &CONTROL_SCHEMES[CONTROLS.load(Ordering::Relaxed)]
+}
 
 const BOOTLOADER_CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
@@ -45,6 +225,11 @@ const BOOTLOADER_CONFIG: BootloaderConfig = {
     config.kernel_stack_size = 256 * 1024; // 256 KiB kernel stack size
     config
 };
+
+// 4 KiB frames at the start of the heap's memory region kept out of the heap, so
+// `BootInfoFrameAllocator` has frames to hand out even if no other region is usable
+const FRAMES_KEPT_FROM_HEAP: u64 = 16;
+#[cfg(not(test))]
 entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
@@ -58,7 +243,6 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
 
     let frame_info = boot_info.framebuffer.as_ref().unwrap().info();
     let framebuffer = boot_info.framebuffer.as_mut().unwrap();
-    screen::init(framebuffer);
 
     for r in boot_info.memory_regions.iter() {
         writeln!(
@@ -100,11 +284,17 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let cr3_page = unsafe { slice::from_raw_parts_mut((cr3 + physical_offset) as *mut usize, 6) };
     writeln!(serial(), "CR3 Page table virtual address {cr3_page:#p}").unwrap();
 
-    allocator::init_heap((physical_offset + usable_region.start) as usize);
+    let heap = usable_region.start + FRAMES_KEPT_FROM_HEAP * 4096..usable_region.end;
+    let heap_size = (heap.end - heap.start) as usize;
+    allocator::init_heap((physical_offset + heap.start) as usize, heap_size);
+    writeln!(serial(), "Heap size: {} KiB", heap_size / 1024).unwrap();
+
+    // the screen's back buffer lives on the heap
+    screen::init(framebuffer);
 
     let rsdp = boot_info.rsdp_addr.take();
     let mut mapper = frame_allocator::init(VirtAddr::new(physical_offset));
-    let mut frame_allocator = BootInfoFrameAllocator::new(&boot_info.memory_regions);
+    let mut frame_allocator = BootInfoFrameAllocator::new(&boot_info.memory_regions, heap);
 
     gdt::init();
 
@@ -116,178 +306,1711 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
         &mut mapper,
         &mut frame_allocator,
     );
+    // The APIC timer is running by now and has counted down for a varying time
+    rng::init(cr3 ^ ((interrupts::timer_count() as u64) << 32));
+    writeln!(serial(), "Heap usage: {}", allocator::stats()).unwrap();
     HandlerTable::new()
         .keyboard(key)
-        .timer(tick)
+        .timer(timer)
         .startup(start)
         .start(lapic_ptr)
 }
 
+/// Horizontal distance between the left edges of two adjacent score digits.
+fn digit_advance(size: usize) -> usize {
+    size + size / 4
+}
+
+/// Number of decimal digits needed to display `score`.
+fn digit_count(score: i32) -> usize {
+    let mut remaining = score.max(0);
+    let mut count = 1;
+    while remaining >= 10 {
+        remaining /= 10;
+        count += 1;
+    }
+    count
+}
+
+/// Total pixel width of `score` when drawn with `draw_score` at the given digit size.
+fn score_width(score: i32, size: usize) -> usize {
+    let digits = digit_count(score);
+    digits * size + (digits - 1) * (size / 4)
+}
+
+/// Draws `score` left-to-right starting at (`x`, `y`), one digit every `size + size/4` pixels.
+/// Each digit is an anti-aliased font glyph centered on its `size` × `size` square.
+/// The whole multi-digit footprint is cleared first so no stale glyph is left behind.
 fn draw_score(score: i32, x: usize, y: usize, size: usize) {
-    match score {
-        0 => screenwriter().draw_zero(x, y, size),
-        1 => screenwriter().draw_one(x, y, size),
-        2 => screenwriter().draw_two(x, y, size),
-        3 => screenwriter().draw_three(x, y, size),
-        _ => {}
+    let digits = digit_count(score);
+    let scale = ((size + LINE_HEIGHT / 2) / LINE_HEIGHT).max(1);
+    let (glyph_width, glyph_height) = ScreenWriter::font_digit_size(scale);
+    let glyph_y = (y + size / 2).saturating_sub(glyph_height / 2);
+    let mut remaining = score.max(0);
+    for i in (0..digits).rev() {
+        let digit_x = x + i * digit_advance(size);
+        screenwriter().clear_score(digit_x, y, size);
+        let glyph_x = (digit_x + size / 2).saturating_sub(glyph_width / 2);
+        screenwriter().draw_digit_font((remaining % 10) as u8, glyph_x, glyph_y, scale);
+        remaining /= 10;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn opponent(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+
+    /// Lower-case name, as used in event records.
+    fn name(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+}
+
+/// The state of the match being played.
+pub fn game_state() -> &'static mut GameState {
+    unsafe { GAME.get_mut() }
+}
+
+/// The balls currently in play.
+fn balls() -> &'static mut [Ball] {
+    game_state().balls()
+}
+
+/// Moves every ball in play by one frame with `game::step`, against the court as it is now.
+fn step_balls() -> game::Outcome {
+    let court = Court {
+        width: screenwriter().width() as f32,
+        height: screenwriter().height() as f32,
+        border: BORDER_THICKNESS as f32,
+        ball_size: ball_size() as f32,
+        max_speed: difficulty().max_speed,
+        time_scale: if slow_motion() { MATCH_POINT_TIME_SCALE } else { 1.0 },
+        gravity: GRAVITY_LEVELS[GRAVITY.load(Ordering::Relaxed)].1,
+        left: paddle(Side::Left),
+        right: paddle(Side::Right),
+    };
+    game::step(game_state(), &court)
+}
+
+/// The paddle on `side` as `game::step` sees it.
+fn paddle(side: Side) -> game::Paddle {
+    let state = *game_state().paddle_state(side);
+    game::Paddle {
+        x: paddle_x(side) as f32,
+        y: *game_state().paddle(side) as f32,
+        width: paddle_width(side) as f32,
+        height: paddle_height(side) as f32,
+        spin: paddle_spin(&state),
+    }
+}
+
+/// Puts a ball at the center of the court heading toward `toward`. In multiball mode a second
+/// ball is served from the same spot with the mirrored velocity.
+fn serve(toward: Side) {
+    let direction = match toward {
+        Side::Left => -1,
+        Side::Right => 1,
+    };
+    // Center the ball itself rather than its top-left corner, and keep it fully on screen
+    let x = (screenwriter().width() / 2)
+        .saturating_sub(ball_size() / 2)
+        .min(screenwriter().width().saturating_sub(ball_size())) as f32;
+    let y = (screenwriter().height() / 2)
+        .saturating_sub(ball_size() / 2)
+        .min(screenwriter().height().saturating_sub(ball_size())) as f32;
+
+    game_state().last_hitter = None;
+    let (speed_x, speed_y) = (difficulty().serve_speed_x, difficulty().serve_speed_y);
+    let balls = &mut game_state().balls;
+    balls[0].reset(x, y, 0.0, 0.0);
+    balls[0].serve(direction, speed_x, speed_y, rng());
+    let mut active = 1;
+    if MULTIBALL.load(Ordering::Relaxed) {
+        balls[1].reset(x, y, 0.0, 0.0);
+        balls[1].serve(-direction, speed_x, speed_y, rng());
+        active = 2;
+    }
+    game_state().active_balls = active;
+}
+
+/// Writes a one-line record of a game event to serial, e.g. `EVENT score left=1 right=0
+/// tick=12345`, unless `LOG_EVENTS` is off. Meant to be grepped from the serial console.
+/// Held back while a screenshot is being sent, see `flush_held_events`.
+fn log_event(event: fmt::Arguments) {
+    if LOG_EVENTS.load(Ordering::Relaxed) {
+        let tick = TICK_COUNT.load(Ordering::Relaxed);
+        if screenshot::in_progress() {
+            let held = unsafe { HELD_EVENTS.get_mut() };
+            held.push(format!("EVENT {event} tick={tick}"));
+        } else {
+            let _ = writeln!(serial(), "EVENT {event} tick={tick}");
+        }
+    }
+}
+
+/// Writes the events held back while a screenshot was being sent, once it is done.
+fn flush_held_events() {
+    if screenshot::in_progress() {
+        return;
+    }
+    for line in unsafe { HELD_EVENTS.get_mut() }.drain(..) {
+        let _ = writeln!(serial(), "{line}");
+    }
+}
+
+/// Gives `scorer` a point and redraws their score. Returns `true` if that won the match.
+fn score_point(scorer: Side) -> bool {
+    let score = game_state().score(scorer);
+    *score += 1;
+    let score = *score;
+    sound::beep_for(SCORE_BEEP.0, SCORE_BEEP.1);
+    game_state().rally = 0;
+    if !DEMO_MODE.load(Ordering::Relaxed) {
+        HIGH_SCORE.fetch_max(score, Ordering::Relaxed);
+    }
+    draw_score(score, score_x(scorer, score), score_y(), score_size());
+    let (left, right) = (game_state().left_score, game_state().right_score);
+    log_event(format_args!("score left={left} right={right}"));
+    let won = end_match_if_won(scorer);
+    if !won && SCORE_FLASH.load(Ordering::Relaxed) {
+        start_flash(scorer.opponent());
+    }
+    won
+}
+
+/// Lights up the half of the court on `side` for `FLASH_TICKS` ticks. The rest of the frame is
+/// drawn over it as usual.
+fn start_flash(side: Side) {
+    let half = screenwriter().width() / 2;
+    let x = match side {
+        Side::Left => 0,
+        Side::Right => half,
+    };
+    screenwriter().flash_strip(x, half);
+    game_state().flash = Some((side, FLASH_TICKS));
+}
+
+/// Counts down a flash started by `start_flash`, repainting the court normally once it is over.
+fn update_flash() {
+    match game_state().flash {
+        Some((side, ticks)) if ticks > 1 => game_state().flash = Some((side, ticks - 1)),
+        Some(_) => {
+            game_state().flash = None;
+            redraw_all();
+        }
+        None => {}
+    }
+}
+
+/// Ends the set if `side`'s score has reached `WIN_SCORE`, and with `WIN_BY_TWO` leads the
+/// opponent by at least two points. If that was the last set `side` needed, ends the match,
+/// otherwise starts the next set. Returns whether the match ended.
+fn end_match_if_won(side: Side) -> bool {
+    if !has_won(side, 0) {
+        return false;
+    }
+    let sets = game_state().sets(side);
+    *sets += 1;
+    if *sets < sets_to_win() {
+        start_next_set(side);
+        return false;
+    }
+    let (left, right) = (game_state().left_score, game_state().right_score);
+    log_event(format_args!("end winner={} left={left} right={right}", side.name()));
+    game_state().ended_at = TICK_COUNT.load(Ordering::Relaxed);
+    replay::finish();
+    END_SCREEN_DRAWN.store(false, Ordering::Relaxed); // paint it on the next tick
+    game_state().phase = STATE_ENDED;
+    true
+}
+
+/// With `DEBUG` on, '1' to '9' set the left score and Shift+'1' to '9' the right score, so the
+/// end of a match can be reached without playing it. Returns whether `key` was one of them.
+fn set_debug_score(key: DecodedKey) -> bool {
+    let DecodedKey::Unicode(c) = key else {
+        return false;
+    };
+    let (side, score) = if let Some(digit) = c.to_digit(10).filter(|&digit| digit > 0) {
+        (Side::Left, digit as i32)
+    } else if let Some(index) = SHIFTED_DIGITS.find(c) {
+        (Side::Right, index as i32 + 1)
+    } else {
+        return false;
+    };
+    *game_state().score(side) = score;
+    // Room for two digits, so nothing of a higher score is left behind
+    let size = score_size();
+    screenwriter().clear_rect(score_x(side, 99), score_y(), score_width(99, size), size);
+    draw_scores();
+    log_event(format_args!("debug-score side={} score={score}", side.name()));
+    end_match_if_won(side);
+    true
+}
+
+/// Left edge of `score` drawn centered over its player's half of the court.
+fn score_x(side: Side, score: i32) -> usize {
+    let center = match side {
+        Side::Left => screenwriter().width() / 4,
+        Side::Right => 3 * screenwriter().width() / 4,
+    };
+    center - score_width(score, score_size()) / 2
+}
+
+/// Top of the scores, below the sets won if the match has more than one set.
+fn score_y() -> usize {
+    if BEST_OF.load(Ordering::Relaxed) > 1 {
+        SCORE_Y + RALLY_SIZE + SETS_GAP
+    } else {
+        SCORE_Y
+    }
+}
+
+/// Draws both scores and, in a match of several sets, the sets won in small digits above them.
+fn draw_scores() {
+    let left_score = game_state().left_score;
+    draw_score(left_score, score_x(Side::Left, left_score), score_y(), score_size());
+    let right_score = game_state().right_score;
+    draw_score(right_score, score_x(Side::Right, right_score), score_y(), score_size());
+    if BEST_OF.load(Ordering::Relaxed) > 1 {
+        let width = screenwriter().width();
+        let left_sets = game_state().left_sets;
+        let left_x = width / 4 - score_width(left_sets, RALLY_SIZE) / 2;
+        draw_score(left_sets, left_x, SCORE_Y, RALLY_SIZE);
+        let right_sets = game_state().right_sets;
+        let right_x = 3 * width / 4 - score_width(right_sets, RALLY_SIZE) / 2;
+        draw_score(right_sets, right_x, SCORE_Y, RALLY_SIZE);
+    }
+}
+
+/// Draws the number of paddle hits in the current rally, centered below the scores.
+fn draw_rally_count() {
+    let rally = game_state().rally;
+    let center = screenwriter().width() / 2;
+    // Clear room for three digits, so no digit of a longer rally is left behind
+    let clear_width = score_width(999, RALLY_SIZE);
+    let y = rally_y();
+    screenwriter().clear_rect(center - clear_width / 2, y, clear_width, RALLY_SIZE);
+    draw_score(rally, center - score_width(rally, RALLY_SIZE) / 2, y, RALLY_SIZE);
+}
+
+/// Top of the rally count, below the scores.
+fn rally_y() -> usize {
+    score_y() + score_size() + 10
+}
+
+/// Whether `side` has won the match once it scores `extra` more points.
+fn has_won(side: Side, extra: i32) -> bool {
+    let score = *game_state().score(side) + extra;
+    let opponent = *game_state().score(side.opponent());
+    let win_score = WIN_SCORE.load(Ordering::Relaxed);
+    wins_set(score, opponent, win_score, WIN_BY_TWO.load(Ordering::Relaxed))
+}
+
+/// Whether `score` points against the `opponent`'s win a set played to `win_score`. With
+/// `win_by_two` the winner also has to lead by two points, so the set goes on past `win_score`.
+fn wins_set(score: i32, opponent: i32, win_score: i32, win_by_two: bool) -> bool {
+    let margin = if win_by_two { 2 } else { 1 };
+    score >= win_score && score - opponent >= margin
+}
+
+/// Sets a player has to win to win the match, a majority of `BEST_OF`.
+fn sets_to_win() -> i32 {
+    BEST_OF.load(Ordering::Relaxed) / 2 + 1
+}
+
+/// Starts a new set after `winner` won one: scores back to 0, balls out of play so the next
+/// serve waits for a countdown, and "Set N" shown during it.
+fn start_next_set(winner: Side) {
+    let state = game_state();
+    let (left, right) = (state.left_sets, state.right_sets);
+    log_event(format_args!("set winner={} left={left} right={right}", winner.name()));
+    state.left_score = 0;
+    state.right_score = 0;
+    state.active_balls = 0;
+    state.set_banner = true;
+    redraw_all();
+}
+
+/// Shows "Set N" below the center while `game_state().set_banner` is on and the serve
+/// countdown runs, and clears it once the countdown is over.
+fn draw_set_banner() {
+    if !game_state().set_banner {
+        return;
+    }
+    let line_height = screenwriter().line_height();
+    let y = screenwriter().height() / 2 + 2 * line_height;
+    let width = screenwriter().text_width("Set 00");
+    let x = (screenwriter().width() / 2).saturating_sub(width / 2);
+    screenwriter().clear_rect(x, y, width, line_height);
+    if game_state().serve_countdown == 0 {
+        game_state().set_banner = false;
+        return;
+    }
+    let mut text = String::new();
+    write!(text, "Set {}", game_state().left_sets + game_state().right_sets + 1).unwrap();
+    screenwriter().draw_centered_text(&text, y);
+}
+
+/// Whether the next point can end the match.
+fn match_point() -> bool {
+    let wins_match = |side: Side| *game_state().sets(side) + 1 >= sets_to_win();
+    (has_won(Side::Left, 1) && wins_match(Side::Left))
+        || (has_won(Side::Right, 1) && wins_match(Side::Right))
+}
+
+/// Whether the balls currently move in slow motion: on match point, with `SLOW_MATCH_POINT`.
+/// The scores only change between rallies, so the whole deciding rally is slowed and the
+/// normal speed comes back with the next serve.
+fn slow_motion() -> bool {
+    SLOW_MATCH_POINT.load(Ordering::Relaxed) && match_point() && !DEMO_MODE.load(Ordering::Relaxed)
+}
+
+/// Shows `MATCH_POINT_TEXT` just above the bottom border while `slow_motion` is on.
+fn draw_match_point() {
+    let line_height = screenwriter().line_height();
+    let y = screenwriter().height() - BORDER_THICKNESS - 2 * line_height;
+    let width = screenwriter().text_width(MATCH_POINT_TEXT);
+    let x = (screenwriter().width() / 2).saturating_sub(width / 2);
+    screenwriter().clear_rect(x, y, width, line_height);
+    if slow_motion() {
+        screenwriter().draw_centered_text(MATCH_POINT_TEXT, y);
+    }
+}
+
+/// Shows the time since the match began as m:ss, centered at the top between the scores. The
+/// mid-line runs through it, so it is drawn again every ball frame; the text itself only
+/// changes once a second.
+fn draw_match_timer() {
+    let seconds = match_tick() / TICKS_PER_SECOND as u64;
+    let mut text = String::new();
+    write!(text, "{}:{:02}", seconds / 60, seconds % 60).unwrap();
+    let width = screenwriter().text_width("000:00");
+    let x = (screenwriter().width() / 2).saturating_sub(width / 2);
+    screenwriter().clear_rect(x, SCORE_Y, width, screenwriter().line_height());
+    screenwriter().draw_centered_text(&text, SCORE_Y);
+}
+
+/// With `WIN_BY_TWO`, shows "Deuce" below the rally count while the scores are level and both
+/// at least `WIN_SCORE - 1`, or which player has the advantage while one leads by a point there.
+/// Clears the line otherwise.
+fn draw_deuce() {
+    let y = rally_y() + RALLY_SIZE + 10;
+    let width = screenwriter().text_width(DEUCE_TEXT_SAMPLE);
+    let x = (screenwriter().width() / 2).saturating_sub(width / 2);
+    let height = screenwriter().line_height();
+    screenwriter().clear_rect(x, y, width, height);
+
+    if !WIN_BY_TWO.load(Ordering::Relaxed) {
+        return;
     }
+    let (left, right) = (game_state().left_score, game_state().right_score);
+    if let Some(text) = deuce_text(left, right, WIN_SCORE.load(Ordering::Relaxed)) {
+        screenwriter().draw_centered_text(text, y);
+    }
+}
+
+/// "Deuce" or who has the advantage at `left` to `right` points in a win-by-two set played to
+/// `win_score`, once both players are a point short of it. `None` before then.
+fn deuce_text(left: i32, right: i32, win_score: i32) -> Option<&'static str> {
+    let threshold = win_score - 1;
+    if left < threshold || right < threshold {
+        return None;
+    }
+    Some(match left.cmp(&right) {
+        core::cmp::Ordering::Equal => "Deuce",
+        core::cmp::Ordering::Greater => "Advantage left",
+        core::cmp::Ordering::Less => "Advantage right",
+    })
+}
+
+/// Passes the ball style picked from `BALL_STYLES` and the color picked from `BALL_COLORS` on
+/// to the screen writer. Both are kept for every later match.
+fn apply_ball_style() {
+    let (_, shape, puck) = BALL_STYLES[BALL_STYLE.load(Ordering::Relaxed)];
+    let color = BALL_COLORS[BALL_COLOR.load(Ordering::Relaxed)].1;
+    let color = color.or_else(|| puck.then(|| screenwriter().theme().paddle));
+    screenwriter().set_ball_style(shape, color);
+}
+
+/// Draws every ball in play together with its trail. Call once per ball frame.
+fn draw_balls() {
+    let mut positions = [(0, 0); MAX_BALLS];
+    for (position, ball) in positions.iter_mut().zip(balls()) {
+        *position = (ball.pixel_x(), ball.pixel_y());
+    }
+    // Tinted toward the color of whoever hit it last
+    let theme = screenwriter().theme();
+    let tint = match game_state().last_hitter {
+        Some(Side::Left) => Some(theme.left_paddle),
+        Some(Side::Right) => Some(theme.right_paddle),
+        None => None,
+    };
+    screenwriter().set_ball_tint(tint);
+    screenwriter().draw_balls_with_trail(&positions[..balls().len()], ball_size());
+}
+
+/// Places both paddles at the vertical center of the screen.
+fn reset_paddles() {
+    let (width, height) = (default_paddle_width(), default_paddle_height());
+    let center = (screenwriter().height() - height) / 2;
+    for side in [Side::Left, Side::Right] {
+        *game_state().paddle(side) = center;
+        *game_state().paddle_state(side) = PaddleState::new(width, height, center);
+    }
+    input::clear();
+    screenwriter().reset_paddle_tracking();
+}
+
+/// Scales the sizes of the paddles, balls and scores from `LAYOUT_HEIGHT` to the height of the
+/// screen. The width is left out, so objects stay square on any aspect ratio.
+fn layout() {
+    let scaled = |size: usize| (size * screenwriter().height() / LAYOUT_HEIGHT).max(1);
+    LAYOUT_PADDLE_WIDTH.store(scaled(PADDLE_WIDTH), Ordering::Relaxed);
+    LAYOUT_PADDLE_HEIGHT.store(scaled(PADDLE_HEIGHT), Ordering::Relaxed);
+    LAYOUT_POWER_UP_GROWTH.store(scaled(POWER_UP_GROWTH), Ordering::Relaxed);
+    LAYOUT_SCORE_SIZE.store(scaled(SCORE_SIZE), Ordering::Relaxed);
+    set_ball_size(scaled(BALL_SIZE));
+}
+
+/// Width of each paddle for the current screen, unless changed by a power-up.
+fn default_paddle_width() -> usize {
+    LAYOUT_PADDLE_WIDTH.load(Ordering::Relaxed)
+}
+
+/// Height of each paddle for the current screen, unless grown by a power-up.
+fn default_paddle_height() -> usize {
+    LAYOUT_PADDLE_HEIGHT.load(Ordering::Relaxed)
 }
 
+/// Digit size of the scores for the current screen.
+fn score_size() -> usize {
+    LAYOUT_SCORE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Startup handler: shows the start menu. Also used to leave a match for the menu.
 fn start() {
+    layout();
+    game_state().left_score = 0;
+    game_state().right_score = 0;
+    sound::silence();
+    replay::finish(); // an abandoned match is still worth having
+    screenwriter().clear();
+    screenwriter().draw_border();
+    MENU_IDLE_TICKS.store(0, Ordering::Relaxed);
+    game_state().phase = STATE_MENU;
+}
+
+/// Starts a match between two computer players that restarts whenever it ends, until a key
+/// is pressed.
+fn start_demo() {
+    DEMO_MODE.store(true, Ordering::Relaxed);
+    begin_match();
+}
+
+/// Resets scores, ball and paddles, draws a fresh court and starts play. Matches other than
+/// the demo are recorded for `replay`.
+fn begin_match() {
+    let seed = ((rng().next_u32() as u64) << 32) | rng().next_u32() as u64;
+    if !DEMO_MODE.load(Ordering::Relaxed) {
+        replay::start_recording(seed);
+    }
+    begin_match_with_seed(seed);
+}
+
+/// `begin_match` with the random number generator seeded with `seed`. Everything a match
+/// depends on is reset here, so the same seed, settings and key presses at the same ticks
+/// play out the same match. Only the remote player is not recorded.
+fn begin_match_with_seed(seed: u64) {
+    rng::init(seed);
+    TICKS_SINCE_BALL_FRAME.store(0, Ordering::Relaxed);
+    game_state().left_score = 0;
+    game_state().right_score = 0;
+    game_state().rally = 0;
+    game_state().flash = None;
+    game_state().left_sets = 0;
+    game_state().right_sets = 0;
+    game_state().set_banner = BEST_OF.load(Ordering::Relaxed) > 1;
+    serve(Side::Right);
+
+    reset_paddles();
+    reset_power_ups();
+    redraw_all();
+    start_serve_countdown();
+    game_state().started_at = TICK_COUNT.load(Ordering::Relaxed);
+    END_SCREEN_DRAWN.store(false, Ordering::Relaxed);
+    game_state().phase = STATE_PLAYING;
+}
+
+/// Repaints the whole court from the current `GameState`: border, mid-line, paddles, balls,
+/// power-up and scores. Used whenever play (re)starts, so nothing left over from the screen
+/// before survives and nothing is missing.
+fn redraw_all() {
+    screenwriter().clear();
+    screenwriter().reset_ball_trail();
+    screenwriter().reset_paddle_tracking();
     screenwriter().draw_pong_game();
+    draw_balls();
+    draw_power_up();
     screenwriter().draw_mid_line();
-    draw_score(0, screenwriter().width() / 4, 10, 30); // Left player
-    draw_score(0, 3 * screenwriter().width() / 4, 10, 30); // Right player
+    screenwriter().draw_border();
+    draw_scores();
+    draw_rally_count();
 }
 
-fn tick() {
-    unsafe {
-        if GAME_STATE.load(Ordering::Relaxed) == 1 {
-            // Game has ended, display win message
-            let message = if LEFT_SCORE.load(Ordering::Relaxed) >= 3 {
-                "Left Player Wins! Press 'r' to restart"
-            } else {
-                "Right Player Wins! Press 'r' to restart"
+/// Counts a tick and returns whether the ball should advance on it, which happens on every
+/// `SPEED_DIVIDER`-th tick.
+fn ball_frame_due() -> bool {
+    let ticks = TICKS_SINCE_BALL_FRAME.fetch_add(1, Ordering::Relaxed) + 1;
+    if ticks < SPEED_DIVIDER.load(Ordering::Relaxed) {
+        return false;
+    }
+    TICKS_SINCE_BALL_FRAME.store(0, Ordering::Relaxed);
+    true
+}
+
+/// How many times per second the ball advances at the selected speed.
+fn ball_frames_per_second() -> u32 {
+    TICKS_PER_SECOND / SPEED_DIVIDER.load(Ordering::Relaxed)
+}
+
+/// Removes any power-up and paddle growth and schedules the first power-up of a match.
+fn reset_power_ups() {
+    game_state().power_up = None;
+    game_state().left_paddle_state.growth_frames = 0;
+    game_state().right_paddle_state.growth_frames = 0;
+    schedule_power_up();
+}
+
+/// Picks a random delay before the next power-up appears.
+fn schedule_power_up() {
+    let fps = ball_frames_per_second();
+    let delay = rng().range(POWER_UP_MIN_DELAY_SECONDS * fps, POWER_UP_MAX_DELAY_SECONDS * fps);
+    game_state().power_up_delay = delay;
+}
+
+/// Advances power-ups by one ball frame: spawns, collects and expires the power-up on the court
+/// and shrinks paddles whose growth has run out.
+fn update_power_ups() {
+    for side in [Side::Left, Side::Right] {
+        let growth = &mut game_state().paddle_state(side).growth_frames;
+        if *growth > 0 {
+            *growth -= 1;
+            if *growth == 0 {
+                set_paddle_height(side, default_paddle_height());
+            }
+        }
+    }
+
+    let power_up = &mut game_state().power_up;
+    let Some(current) = power_up else {
+        let delay = &mut game_state().power_up_delay;
+        if *delay > 0 {
+            *delay -= 1;
+            return;
+        }
+        // Somewhere between the paddles, clear of the border
+        let width = screenwriter().width() as u32;
+        let height = screenwriter().height() as u32;
+        let size = POWER_UP_SIZE as u32;
+        let border = BORDER_THICKNESS as u32;
+        *power_up = Some(PowerUp {
+            x: rng().range(width / 4, 3 * width / 4 - size) as usize,
+            y: rng().range(border, height - border - size) as usize,
+            frames_left: POWER_UP_LIFETIME_SECONDS * ball_frames_per_second(),
+        });
+        return;
+    };
+
+    // Only a ball someone has hit can collect it, so it is clear whose paddle grows
+    let hitter = game_state().last_hitter;
+    let (x, y, size) = (current.x as f32, current.y as f32, POWER_UP_SIZE as f32);
+    let ball_size = ball_size() as f32;
+    let hit = hitter.is_some()
+        && balls().iter().any(|ball| ball.intersects_paddle(ball_size, x, y, size, size));
+    if !hit && current.frames_left > 0 {
+        current.frames_left -= 1;
+        return;
+    }
+
+    screenwriter().clear_rect(current.x, current.y, POWER_UP_SIZE, POWER_UP_SIZE);
+    *power_up = None;
+    schedule_power_up();
+    if let (true, Some(side)) = (hit, hitter) {
+        let growth = LAYOUT_POWER_UP_GROWTH.load(Ordering::Relaxed);
+        set_paddle_height(side, default_paddle_height() + growth);
+        let frames = POWER_UP_SECONDS * ball_frames_per_second();
+        game_state().paddle_state(side).growth_frames = frames;
+        log_event(format_args!("power-up side={}", side.name()));
+    }
+}
+
+/// Draws the power-up on the court, if there is one.
+fn draw_power_up() {
+    if let Some(power_up) = &game_state().power_up {
+        screenwriter().draw_power_up(power_up.x, power_up.y, POWER_UP_SIZE);
+    }
+}
+
+/// Resizes the paddle on `side`, moving it as needed to keep it on screen.
+fn set_paddle_height(side: Side, height: usize) {
+    game_state().paddle_state(side).height = height;
+    let paddle = game_state().paddle(side);
+    *paddle = move_paddle(side, *paddle, 0);
+}
+
+/// Holds the ball for `SERVE_COUNTDOWN_SECONDS` before the next serve.
+fn start_serve_countdown() {
+    game_state().serve_countdown = SERVE_COUNTDOWN_SECONDS * ball_frames_per_second();
+}
+
+/// Advances the serve countdown by one ball frame, showing the remaining seconds above the center of
+/// the court. Returns `true` while the ball has to be held.
+fn serve_countdown() -> bool {
+    let remaining = &mut game_state().serve_countdown;
+    if *remaining == 0 {
+        return false;
+    }
+    *remaining -= 1;
+    let remaining = *remaining;
+
+    let x = (screenwriter().width() - COUNTDOWN_SIZE) / 2;
+    let y = screenwriter().height() / 2 - 2 * COUNTDOWN_SIZE;
+    screenwriter().clear_score(x, y, COUNTDOWN_SIZE);
+    if remaining > 0 {
+        let seconds = remaining.div_ceil(ball_frames_per_second());
+        screenwriter().draw_digit(seconds as u8, x, y, COUNTDOWN_SIZE);
+    }
+    remaining > 0
+}
+
+fn draw_menu() {
+    let start_y = screenwriter().height() / 3;
+    let line_height = screenwriter().line_height();
+    screenwriter().draw_centered_text("P O N G", start_y);
+    draw_high_score(start_y + line_height);
+    screenwriter().draw_centered_text("Press SPACE to start", start_y + 2 * line_height);
+    draw_sound_state(start_y + 3 * line_height);
+    draw_settings(start_y + 4 * line_height);
+}
+
+/// Shows at height `y` whether sound is muted, and how to change it.
+fn draw_sound_state(y: usize) {
+    let text = if sound::SOUND_ON.load(Ordering::Relaxed) {
+        "Sound: on - press 'm' to mute"
+    } else {
+        "MUTE - press 'm' for sound"
+    };
+    // The texts differ in length, so clear the longer one first
+    let width = screenwriter().text_width("Sound: on - press 'm' to mute");
+    let x = (screenwriter().width() / 2).saturating_sub(width / 2);
+    screenwriter().clear_rect(x, y, width, screenwriter().line_height());
+    screenwriter().draw_centered_text(text, y);
+}
+
+/// Paints the game-over screen: the winner, both final scores, how long the match took and what
+/// to press next. The settings are left to the menu, as they would not fit below at 1080p.
+fn draw_end_screen() {
+    let (left, right) = (game_state().left_score, game_state().right_score);
+    let winner = if left > right {
+        "Left Player Wins!"
+    } else {
+        "Right Player Wins!"
+    };
+    screenwriter().clear();
+    screenwriter().draw_border();
+    let line_height = screenwriter().line_height();
+    let mut y = screenwriter().draw_centered_text(winner, screenwriter().height() / 4);
+    y += line_height;
+
+    let width = screenwriter().width();
+    let left_x = width * 3 / 8 - score_width(left, FINAL_SCORE_SIZE) / 2;
+    draw_score(left, left_x, y, FINAL_SCORE_SIZE);
+    let right_x = width * 5 / 8 - score_width(right, FINAL_SCORE_SIZE) / 2;
+    draw_score(right, right_x, y, FINAL_SCORE_SIZE);
+    y += FINAL_SCORE_SIZE + line_height;
+
+    let ticks = game_state().ended_at - game_state().started_at;
+    let seconds = ticks / TICKS_PER_SECOND as u64;
+    let mut duration = String::new();
+    write!(duration, "Match time: {}:{:02}", seconds / 60, seconds % 60).unwrap();
+    y = screenwriter().draw_centered_text(&duration, y);
+    if BEST_OF.load(Ordering::Relaxed) > 1 {
+        let mut sets = String::new();
+        let (left_sets, right_sets) = (game_state().left_sets, game_state().right_sets);
+        write!(sets, "Sets: {left_sets} - {right_sets}").unwrap();
+        y = screenwriter().draw_centered_text(&sets, y);
+    }
+    y = screenwriter().draw_centered_text("Press 'r' or SPACE for a rematch, Esc for the menu", y);
+    draw_high_score(y + line_height);
+}
+
+/// Draws the best score reached since boot on the line at `y`.
+fn draw_high_score(y: usize) {
+    let mut text = String::new();
+    write!(text, "Best: {}", HIGH_SCORE.load(Ordering::Relaxed)).unwrap();
+    screenwriter().draw_centered_text(&text, y);
+}
+
+/// Draws the match settings, one per line, starting at `y`.
+fn draw_settings(y: usize) {
+    let line_height = screenwriter().line_height();
+
+    // Match length, adjustable with '+' and '-'
+    let mut setting = String::new();
+    write!(
+        setting,
+        "First to {:>2} - press '+'/'-' to change",
+        WIN_SCORE.load(Ordering::Relaxed)
+    )
+    .unwrap();
+    screenwriter().draw_centered_text(&setting, y);
+
+    let mode = if SINGLE_PLAYER.load(Ordering::Relaxed) {
+        "Mode: 1 player  - press '1' to toggle"
+    } else {
+        "Mode: 2 players - press '1' to toggle"
+    };
+    screenwriter().draw_centered_text(mode, y + line_height);
+
+    let speed = match SPEED_DIVIDER.load(Ordering::Relaxed) {
+        SPEED_EASY => "Speed: Easy   - press 's' to change",
+        SPEED_FAST => "Speed: Fast   - press 's' to change",
+        _ => "Speed: Normal - press 's' to change",
+    };
+    screenwriter().draw_centered_text(speed, y + 2 * line_height);
+
+    let balls = if MULTIBALL.load(Ordering::Relaxed) {
+        "Balls: 2 - press 'b' to toggle"
+    } else {
+        "Balls: 1 - press 'b' to toggle"
+    };
+    screenwriter().draw_centered_text(balls, y + 3 * line_height);
+
+    let mut level = String::new();
+    write!(level, "Difficulty: {} - press 'd' to change", difficulty().name).unwrap();
+    screenwriter().draw_centered_text(&level, y + 4 * line_height);
+
+    let mut keys = String::new();
+    write!(keys, "Controls: {} - press 'i' to change", controls().name).unwrap();
+    screenwriter().draw_centered_text(&keys, y + 5 * line_height);
+
+    let mut theme = String::new();
+    write!(theme, "Theme: {} - press 't' to change", screenwriter().theme().name).unwrap();
+    screenwriter().draw_centered_text(&theme, y + 6 * line_height);
+
+    let remote = if REMOTE_PLAYER.load(Ordering::Relaxed) {
+        "Right paddle over serial: on  - press 'n' to toggle"
+    } else {
+        "Right paddle over serial: off - press 'n' to toggle"
+    };
+    screenwriter().draw_centered_text(remote, y + 7 * line_height);
+
+    let margin = if WIN_BY_TWO.load(Ordering::Relaxed) {
+        "Win by two: on  - press 'a' to toggle"
+    } else {
+        "Win by two: off - press 'a' to toggle"
+    };
+    screenwriter().draw_centered_text(margin, y + 8 * line_height);
+
+    let slow = if SLOW_MATCH_POINT.load(Ordering::Relaxed) {
+        "Slow-motion match point: on  - press 'o' to toggle"
+    } else {
+        "Slow-motion match point: off - press 'o' to toggle"
+    };
+    screenwriter().draw_centered_text(slow, y + 9 * line_height);
+
+    let mut gravity = String::new();
+    let name = GRAVITY_LEVELS[GRAVITY.load(Ordering::Relaxed)].0;
+    write!(gravity, "Gravity: {name} - press 'g' to change").unwrap();
+    screenwriter().draw_centered_text(&gravity, y + 10 * line_height);
+
+    let mut style = String::new();
+    let name = BALL_STYLES[BALL_STYLE.load(Ordering::Relaxed)].0;
+    write!(style, "Ball: {name} - press 'u' to change").unwrap();
+    screenwriter().draw_centered_text(&style, y + 11 * line_height);
+
+    let flash = if SCORE_FLASH.load(Ordering::Relaxed) {
+        "Flash on points: on  - press 'h' to toggle"
+    } else {
+        "Flash on points: off - press 'h' to toggle"
+    };
+    screenwriter().draw_centered_text(flash, y + 12 * line_height);
+
+    let mut sets = String::new();
+    match BEST_OF.load(Ordering::Relaxed) {
+        1 => write!(sets, "Match: single set - press 'e' to change").unwrap(),
+        best_of => write!(sets, "Match: best of {best_of} sets - press 'e' to change").unwrap(),
+    }
+    screenwriter().draw_centered_text(&sets, y + 13 * line_height);
+
+    let mut color = String::new();
+    let name = BALL_COLORS[BALL_COLOR.load(Ordering::Relaxed)].0;
+    write!(color, "Ball color: {name} - press 'c' to change").unwrap();
+    screenwriter().draw_centered_text(&color, y + 14 * line_height);
+
+    let contrast = if HIGH_CONTRAST_TEXT.load(Ordering::Relaxed) {
+        "High-contrast text: on  - press 'x' to toggle"
+    } else {
+        "High-contrast text: off - press 'x' to toggle"
+    };
+    screenwriter().draw_centered_text(contrast, y + 15 * line_height);
+}
+
+/// Applies `key` if it changes one of the settings shown by `draw_settings`.
+/// Returns whether the key was used.
+fn handle_setting_key(key: DecodedKey) -> bool {
+    match key {
+        DecodedKey::Unicode('+') => adjust_win_score(1),
+        DecodedKey::Unicode('-') => adjust_win_score(-1),
+        DecodedKey::Unicode('1') => {
+            SINGLE_PLAYER.fetch_xor(true, Ordering::Relaxed);
+            REMOTE_PLAYER.store(false, Ordering::Relaxed); // only one can have the right paddle
+        }
+        DecodedKey::Unicode('s' | 'S') => {
+            let next = match SPEED_DIVIDER.load(Ordering::Relaxed) {
+                SPEED_EASY => SPEED_NORMAL,
+                SPEED_NORMAL => SPEED_FAST,
+                _ => SPEED_EASY,
             };
-            let char_width = 8;
-            let text_width = message.len() * char_width;
-            let start_x = (screenwriter().width() / 2) - (text_width / 2); // Center horizontally
-            let start_y = screenwriter().height() / 2; // Center vertically
-            screenwriter().set_position(start_x, start_y);
-            write!(screenwriter(), "{}", message).unwrap();
+            SPEED_DIVIDER.store(next, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('n' | 'N') => {
+            REMOTE_PLAYER.fetch_xor(true, Ordering::Relaxed);
+            SINGLE_PLAYER.store(false, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('a' | 'A') => {
+            WIN_BY_TWO.fetch_xor(true, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('o' | 'O') => {
+            SLOW_MATCH_POINT.fetch_xor(true, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('g' | 'G') => {
+            let next = (GRAVITY.load(Ordering::Relaxed) + 1) % GRAVITY_LEVELS.len();
+            GRAVITY.store(next, Ordering::Relaxed);
+            screenwriter().clear(); // the names differ in length
+            screenwriter().draw_border();
+        }
+        DecodedKey::Unicode('u' | 'U') => {
+            let next = (BALL_STYLE.load(Ordering::Relaxed) + 1) % BALL_STYLES.len();
+            BALL_STYLE.store(next, Ordering::Relaxed);
+            apply_ball_style();
+            screenwriter().clear(); // the names differ in length
+            screenwriter().draw_border();
+        }
+        DecodedKey::Unicode('c' | 'C') => {
+            let next = (BALL_COLOR.load(Ordering::Relaxed) + 1) % BALL_COLORS.len();
+            BALL_COLOR.store(next, Ordering::Relaxed);
+            apply_ball_style();
+            screenwriter().clear(); // the names differ in length
+            screenwriter().draw_border();
+        }
+        DecodedKey::Unicode('x' | 'X') => {
+            let on = !HIGH_CONTRAST_TEXT.fetch_xor(true, Ordering::Relaxed);
+            screenwriter().set_high_contrast_text(on);
+        }
+        DecodedKey::Unicode('h' | 'H') => {
+            SCORE_FLASH.fetch_xor(true, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('e' | 'E') => {
+            let best_of = BEST_OF.load(Ordering::Relaxed);
+            let index = BEST_OF_CHOICES.iter().position(|&n| n == best_of).unwrap_or(0);
+            BEST_OF.store(BEST_OF_CHOICES[(index + 1) % BEST_OF_CHOICES.len()], Ordering::Relaxed);
+            screenwriter().clear(); // the texts differ in length
+            screenwriter().draw_border();
+        }
+        DecodedKey::Unicode('b' | 'B') => {
+            MULTIBALL.fetch_xor(true, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('d' | 'D') => {
+            let next = (DIFFICULTY.load(Ordering::Relaxed) + 1) % DIFFICULTIES.len();
+            DIFFICULTY.store(next, Ordering::Relaxed);
+        }
+        DecodedKey::Unicode('i' | 'I') => {
+            let next = (CONTROLS.load(Ordering::Relaxed) + 1) % CONTROL_SCHEMES.len();
+            CONTROLS.store(next, Ordering::Relaxed);
+            screenwriter().clear(); // the names differ in length
+            screenwriter().draw_border();
+        }
+        DecodedKey::Unicode('t' | 'T') => {
+            let next = (THEME_INDEX.load(Ordering::Relaxed) + 1) % THEMES.len();
+            THEME_INDEX.store(next, Ordering::Relaxed);
+            screenwriter().set_theme(THEMES[next]);
+            apply_ball_style(); // a puck follows the paddle color
+            screenwriter().clear(); // repaint the background in the new color
+            screenwriter().draw_border();
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Timer interrupt handler: runs `tick` `TICKS_PER_SECOND` times a second on average. Every
+/// interrupt earns `TICKS_PER_SECOND` credit and every tick costs `TIMER_RATE`, with the
+/// remainder carried over, so a faster timer skips some interrupts and a slower one runs
+/// several ticks on some of them.
+fn timer() {
+    measure_timer_rate();
+    let rate = TIMER_RATE.load(Ordering::Relaxed).max(1);
+    let mut credit = TICK_CREDIT.load(Ordering::Relaxed) + TICKS_PER_SECOND;
+    let mut ticks = 0;
+    while credit >= rate && ticks < MAX_TICKS_PER_INTERRUPT {
+        credit -= rate;
+        ticks += 1;
+        tick();
+    }
+    TICK_CREDIT.store(credit.min(rate), Ordering::Relaxed); // drop what could not be caught up
+}
+
+/// Counts timer interrupts between changes of the real-time clock's seconds into `TIMER_RATE`.
+/// The first, partial second is not counted.
+fn measure_timer_rate() {
+    let interrupts = TIMER_INTERRUPTS.fetch_add(1, Ordering::Relaxed) + 1;
+    let second = rtc::seconds() as u32;
+    let previous = TIMER_RTC_SECOND.swap(second, Ordering::Relaxed);
+    if previous == second {
+        return;
+    }
+    if previous != u32::MAX {
+        TIMER_RATE.store(interrupts, Ordering::Relaxed);
+    }
+    TIMER_INTERRUPTS.store(0, Ordering::Relaxed);
+}
+
+/// Ticks since the current match began, the clock `replay` records key presses by.
+fn match_tick() -> u64 {
+    TICK_COUNT.load(Ordering::Relaxed) - game_state().started_at
+}
+
+fn tick() {
+    // A played back key is handed in where it arrived between the ticks when recorded
+    while let Some(key) = replay::next_key(match_tick()) {
+        handle_key(key);
+    }
+    sound::tick();
+    screenshot::tick();
+    flush_held_events();
+    update_fps_counter();
+    if CALIBRATING.load(Ordering::Relaxed) {
+        return; // the test pattern stays as drawn
+    }
+    if game_state().phase == STATE_MENU {
+        let idle_ticks = MENU_IDLE_TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+        if idle_ticks >= DEMO_IDLE_SECONDS * TICKS_PER_SECOND {
+            start_demo();
             return;
         }
+        draw_menu();
+        screenwriter().present();
+        return;
+    }
 
-        // Clear the ball's old position
-        screenwriter().clear_ball(BALL_X, BALL_Y, BALL_SIZE);
-
-        // Calculate new ball position
-        let new_ball_x = (BALL_X as isize) + BALL_SPEED_X;
-        let new_ball_y = (BALL_Y as isize) + BALL_SPEED_Y;
-
-        // Score display positions
-        let left_score_x = screenwriter().width() / 4;
-        let right_score_x = 3 * screenwriter().width() / 4;
-        let score_y = 10;
-        let score_size = 30;
-
-        // Check for scoring conditions
-        if new_ball_x < 0 {
-            // Right player scores
-            let right_score = RIGHT_SCORE.fetch_add(1, Ordering::Relaxed) + 1; // New score
-            screenwriter().clear_score(right_score_x, score_y, score_size);
-            draw_score(right_score, right_score_x, score_y, score_size);
-            BALL_X = screenwriter().width() / 2;
-            BALL_Y = screenwriter().height() / 2;
-            BALL_SPEED_X = 5;
-            BALL_SPEED_Y = 3;
-            if right_score >= 3 {
-                GAME_STATE.store(1, Ordering::Relaxed);
-            }
-        } else if new_ball_x + BALL_SIZE as isize > screenwriter().width() as isize {
-            // Left player scores
-            let left_score = LEFT_SCORE.fetch_add(1, Ordering::Relaxed) + 1; // New score
-            screenwriter().clear_score(left_score_x, score_y, score_size);
-            draw_score(left_score, left_score_x, score_y, score_size);
-            BALL_X = screenwriter().width() / 2;
-            BALL_Y = screenwriter().height() / 2;
-            BALL_SPEED_X = -5;
-            BALL_SPEED_Y = -3;
-            if left_score >= 3 {
-                GAME_STATE.store(1, Ordering::Relaxed);
-            }
-        } else {
-            BALL_X = new_ball_x as usize;
-
-            if new_ball_y < 0 {
-                BALL_Y = 0;
-                BALL_SPEED_Y = -BALL_SPEED_Y; // Bounce downward
-            } else if new_ball_y + BALL_SIZE as isize > screenwriter().height() as isize {
-                BALL_Y = (screenwriter().height() - BALL_SIZE) as usize; // Clamp to bottom
-                BALL_SPEED_Y = -BALL_SPEED_Y; // Bounce upward
-            } else {
-                BALL_Y = new_ball_y as usize; // Normal movement within bounds
-            }
+    if game_state().phase == STATE_ENDED {
+        if DEMO_MODE.load(Ordering::Relaxed) {
+            begin_match(); // the demo loops forever
+            return;
+        }
+
+        // Nothing moves on the game-over screen, so it is only painted when it changes
+        if !END_SCREEN_DRAWN.swap(true, Ordering::Relaxed) {
+            draw_end_screen();
+            screenwriter().present();
+        }
+        return;
+    }
+
+    if game_state().phase == STATE_PAUSED {
+        // Hold everything in place until 'p' is pressed again
+        screenwriter().draw_centered_text(PAUSED_TEXT, screenwriter().height() / 2);
+        screenwriter().present();
+        return;
+    }
+
+    update_flash();
+
+    if DEMO_MODE.load(Ordering::Relaxed) {
+        move_ai_paddle(Side::Left);
+        move_ai_paddle(Side::Right);
+    } else {
+        if REMOTE_PLAYER.load(Ordering::Relaxed) {
+            poll_remote_player();
+        }
+        apply_queued_presses();
+        move_held_paddles();
+        if SINGLE_PLAYER.load(Ordering::Relaxed) {
+            move_ai_paddle(Side::Right);
+        }
+    }
 
-            // Right paddle collision
-            if BALL_SPEED_X > 0
-                && new_ball_x + (BALL_SIZE + 15) as isize
-                    >= screenwriter().width() as isize - PADDLE_WIDTH as isize
-                && new_ball_y + BALL_SIZE as isize > PADDLE_RIGHT as isize
-                && new_ball_y < (PADDLE_RIGHT + PADDLE_HEIGHT) as isize
-            {
-                BALL_SPEED_X = -BALL_SPEED_X; // Bounce left
+    if !ball_frame_due() {
+        // Paddles move every tick, the ball only every SPEED_DIVIDER-th one
+        screenwriter().draw_pong_game();
+        screenwriter().present();
+        return;
+    }
+
+    if serve_countdown() {
+        // Balls wait at the center, paddles can already move
+        draw_balls();
+        draw_set_banner();
+        screenwriter().draw_pong_game();
+        screenwriter().present();
+        return;
+    }
+
+    // Move every ball; each one leaving the court scores a point and is taken out of play
+    let mut last_scorer = Side::Left;
+    for event in step_balls().into_iter().flatten() {
+        match event {
+            Event::Hit(side) => {
+                sound::beep_for(PADDLE_HIT_BEEP.0, PADDLE_HIT_BEEP.1);
+                game_state().rally += 1;
+                log_event(format_args!("hit side={}", side.name()));
+                game_state().last_hitter = Some(side);
             }
-            // Left paddle collision
-            else if BALL_SPEED_X < 0
-                && new_ball_x <= (PADDLE_WIDTH + 15) as isize
-                && new_ball_y + BALL_SIZE as isize > PADDLE_LEFT as isize
-                && new_ball_y < (PADDLE_LEFT + PADDLE_HEIGHT) as isize
-            {
-                BALL_SPEED_X = -BALL_SPEED_X; // Bounce right
+            Event::Bounce => sound::beep_for(WALL_BOUNCE_BEEP.0, WALL_BOUNCE_BEEP.1),
+            Event::Score(scorer) => {
+                last_scorer = scorer;
+                if score_point(scorer) {
+                    break;
+                }
             }
         }
+    }
 
-        // Draw the ball at the new position
-        screenwriter().draw_ball(BALL_X, BALL_Y, BALL_SIZE);
+    // Serve again once the last ball is gone, toward the player who conceded the point
+    if balls().is_empty() && game_state().phase == STATE_PLAYING {
+        serve(last_scorer.opponent());
+        start_serve_countdown();
+    }
 
-        // Redraw game elements
-        screenwriter().draw_pong_game();
-        screenwriter().draw_mid_line();
+    if game_state().phase == STATE_PLAYING {
+        update_power_ups();
+    }
+    draw_set_banner(); // clears it once the countdown is over
+
+    // Draw the balls at their new positions; this also clears the end of their trails
+    draw_balls();
+    draw_power_up();
+
+    // Redraw game elements
+    screenwriter().draw_pong_game();
+    screenwriter().redraw_mid_line();
+    screenwriter().draw_border();
+
+    // Always draw the current scores
+    draw_scores();
+    draw_rally_count();
+    draw_deuce();
+    draw_match_point();
+    draw_match_timer();
 
-        // Always draw the current scores
-        screenwriter().clear_score(left_score_x, score_y, score_size);
-        draw_score(LEFT_SCORE.load(Ordering::Relaxed), left_score_x, score_y, score_size);
-        screenwriter().clear_score(right_score_x, score_y, score_size);
-        draw_score(RIGHT_SCORE.load(Ordering::Relaxed), right_score_x, score_y, score_size);
+    if DEMO_MODE.load(Ordering::Relaxed) {
+        screenwriter().draw_centered_text(DEMO_TEXT, screenwriter().height() * 3 / 4);
     }
+
+    screenwriter().present();
 }
 
 
+/// Keyboard handler. The full key map:
+/// - Space confirms, see `confirm`
+/// - Esc leaves a match for the menu, 'p' pauses and resumes
+/// - The paddle keys of the current `Controls`; 'r' starts a rematch once a match has ended
+/// - On the menu, the setting keys listed by `draw_settings`
+/// - Anywhere: 'f' FPS counter, 'l' event log (not in a match if 'l' moves a paddle), 'm' mute,
+///   F9 debug mode, F12 screenshot
+/// - On the menu: F1 framebuffer test pattern, which ignores every other key but F12
+/// - On the menu and the game-over screen: F5 plays back the last recorded match, see `replay`
+/// - In debug mode: '1'-'9' set the left score, shifted digits the right one
+///
+/// Keys pressed during a match are recorded. While a match is played back, only Esc (which
+/// stops it) and F12 are taken from the keyboard.
 fn key(key: DecodedKey) {
-    unsafe {
-        if GAME_STATE.load(Ordering::Relaxed) == 1 {
-            if let DecodedKey::Unicode('r') = key {
-                // Reset game state
-                LEFT_SCORE.store(0, Ordering::Relaxed);
-                RIGHT_SCORE.store(0, Ordering::Relaxed);
-                BALL_X = screenwriter().width() / 2;
-                BALL_Y = screenwriter().height() / 2;
-                BALL_SPEED_X = 5;
-                BALL_SPEED_Y = 3;
-                PADDLE_LEFT = 100;
-                PADDLE_RIGHT = 500;
-                GAME_STATE.store(0, Ordering::Relaxed);
-                screenwriter().clear();
-                start();
+    if replay::is_playing() {
+        if let DecodedKey::Unicode('\u{1b}') | DecodedKey::RawKey(KeyCode::Escape) = key {
+            replay::finish();
+        } else if key != DecodedKey::RawKey(KeyCode::F12) {
+            return;
+        }
+    }
+    replay::record(match_tick(), key);
+    handle_key(key);
+}
+
+fn handle_key(key: DecodedKey) {
+    if key == DecodedKey::RawKey(KeyCode::F12) {
+        screenshot::start(); // works anywhere, even in the demo
+        return;
+    }
+    if DEMO_MODE.swap(false, Ordering::Relaxed) {
+        begin_match(); // any key leaves the demo for a real match
+        return;
+    }
+
+    if key == DecodedKey::RawKey(KeyCode::F1) && game_state().phase == STATE_MENU {
+        toggle_calibration();
+        return;
+    }
+    if CALIBRATING.load(Ordering::Relaxed) {
+        return; // only F1 leaves the test pattern
+    }
+
+    if let DecodedKey::Unicode('f' | 'F') = key {
+        toggle_fps_counter();
+        return;
+    }
+    // Unless 'l' moves a paddle in the current controls
+    let moves_paddle = controls().uses(DecodedKey::Unicode('l'));
+    let in_match = game_state().phase == STATE_PLAYING;
+    if matches!(key, DecodedKey::Unicode('l' | 'L')) && !(moves_paddle && in_match) {
+        LOG_EVENTS.fetch_xor(true, Ordering::Relaxed);
+        return;
+    }
+    if let DecodedKey::Unicode('m' | 'M') = key {
+        sound::toggle_sound();
+        return;
+    }
+    if key == DecodedKey::RawKey(KeyCode::F9) {
+        DEBUG.fetch_xor(true, Ordering::Relaxed);
+        return;
+    }
+    if key == DecodedKey::Unicode(' ') {
+        confirm();
+        return;
+    }
+    // The layout reports Escape as the ESC character rather than a raw key
+    if let DecodedKey::Unicode('\u{1b}') | DecodedKey::RawKey(KeyCode::Escape) = key {
+        if game_state().phase != STATE_MENU {
+            start(); // back to the menu, abandoning the match
+        }
+        return;
+    }
+
+    match game_state().phase {
+        STATE_MENU | STATE_ENDED if key == DecodedKey::RawKey(KeyCode::F5) => {
+            if let Some(seed) = replay::start_playback() {
+                begin_match_with_seed(seed);
+            }
+            return;
+        }
+        STATE_MENU => {
+            MENU_IDLE_TICKS.store(0, Ordering::Relaxed);
+            handle_setting_key(key);
+            return;
+        }
+        STATE_ENDED => {
+            if key == DecodedKey::Unicode('r') {
+                begin_match();
             }
             return;
         }
+        _ => {}
+    }
 
-        match key {
-            DecodedKey::Unicode(c) if c == 'W' || c == 'w' => {
-                if PADDLE_LEFT > 25 {
-                    PADDLE_LEFT -= 25;
-                }
+    if let DecodedKey::Unicode('p' | 'P') = key {
+        toggle_pause();
+        return;
+    }
+    if game_state().phase == STATE_PAUSED {
+        return; // paddles stay put while paused
+    }
+    if DEBUG.load(Ordering::Relaxed) && set_debug_score(key) {
+        return;
+    }
+
+    // Presses are queued for tick(), which moves the paddles for as long as their key repeats
+    let controls = controls();
+    let key = match key {
+        DecodedKey::Unicode(c) => DecodedKey::Unicode(c.to_ascii_lowercase()),
+        other => other,
+    };
+    if key == controls.left_up {
+        input::push((Side::Left, -1));
+    } else if key == controls.left_down {
+        input::push((Side::Left, 1));
+    } else if SINGLE_PLAYER.load(Ordering::Relaxed) || REMOTE_PLAYER.load(Ordering::Relaxed) {
+        // The right paddle's keys would fight the computer or the remote player
+    } else if key == controls.right_up {
+        input::push((Side::Right, -1));
+    } else if key == controls.right_down {
+        input::push((Side::Right, 1));
+    }
+}
+
+/// Starts (or keeps) a paddle moving in `direction` for the next `PADDLE_HOLD_TICKS` ticks.
+/// Turning around starts again from `PADDLE_MIN_STEP`.
+fn hold_paddle(side: Side, direction: i32) {
+    let paddle = game_state().paddle_state(side);
+    if paddle.direction != direction {
+        paddle.direction = direction;
+        paddle.moving_for = 0;
+    }
+    paddle.hold = PADDLE_HOLD_TICKS;
+}
+
+/// Applies the paddle key presses `key()` queued since the last tick, a few per tick.
+fn apply_queued_presses() {
+    for (side, direction) in input::pop_for_tick() {
+        hold_paddle(side, direction);
+    }
+}
+
+/// Moves each paddle whose key was pressed recently, speeding up while the key stays held and
+/// keeping the paddle on screen.
+fn move_held_paddles() {
+    for side in [Side::Left, Side::Right] {
+        let position = *game_state().paddle(side);
+        *game_state().paddle(side) = step_held_paddle(side, position);
+    }
+}
+
+/// New position of the paddle on `side` at `position` after one tick of held-key movement.
+/// A paddle that stops has to build up speed again.
+fn step_held_paddle(side: Side, position: usize) -> usize {
+    let paddle = game_state().paddle_state(side);
+    if paddle.hold == 0 {
+        paddle.moving_for = 0;
+        return position;
+    }
+    paddle.hold -= 1;
+    let step = paddle_step(paddle.moving_for);
+    paddle.moving_for += 1;
+    let direction = paddle.direction as isize;
+    move_paddle(side, position, direction * step)
+}
+
+/// Pixels a paddle moves on its `ticks`-th tick of moving the same way.
+fn paddle_step(ticks: u32) -> isize {
+    let extra = (ticks / PADDLE_ACCELERATION_TICKS) as isize;
+    (PADDLE_MIN_STEP + extra).min(PADDLE_MAX_STEP)
+}
+
+/// Ball and computer player tuning for one difficulty level.
+struct Difficulty {
+    name: &'static str,
+    serve_speed_x: f32, // pixels per ball frame when served
+    serve_speed_y: f32,
+    max_speed: f32,            // ball speed cap reached through paddle hits
+    ai_max_speed: isize,       // pixels per tick the computer paddle may move
+    ai_reaction_ticks: u64,    // how often the computer paddle looks at the ball again
+    ai_mistake_per_mille: u32, // chance of a misjudged look, see `move_ai_paddle`
+}
+
+const DIFFICULTIES: [Difficulty; 3] = [
+    Difficulty {
+        name: "Easy",
+        serve_speed_x: 4.0,
+        serve_speed_y: 2.5,
+        max_speed: 9.0,
+        ai_max_speed: 3,
+        ai_reaction_ticks: 12,
+        ai_mistake_per_mille: 60,
+    },
+    Difficulty {
+        name: "Normal",
+        serve_speed_x: 5.0,
+        serve_speed_y: 3.0,
+        max_speed: 12.0,
+        ai_max_speed: 4,
+        ai_reaction_ticks: 6,
+        ai_mistake_per_mille: 20,
+    },
+    Difficulty {
+        name: "Hard",
+        serve_speed_x: 6.0,
+        serve_speed_y: 3.5,
+        max_speed: 15.0,
+        ai_max_speed: 6,
+        ai_reaction_ticks: 1,
+        ai_mistake_per_mille: 1,
+    },
+];
+
+/// The difficulty picked on the menu.
+fn difficulty() -> &'static Difficulty {
+    &DIFFICULTIES[DIFFICULTY.load(Ordering::Relaxed)]
+}
+
+/// Current width and height of every ball.
+pub fn ball_size() -> usize {
+    CURRENT_BALL_SIZE.load(Ordering::Relaxed)
+}
+
+/// Changes the size of every ball to `size` × `size` pixels (at least 1). Collisions follow
+/// the new size at once, as they are worked out from the ball's and the paddles' rectangles
+/// rather than from fixed offsets. Takes effect on screen from the next match, which starts
+/// with a cleared court, so no trail of the old size is left behind.
+pub fn set_ball_size(size: usize) {
+    CURRENT_BALL_SIZE.store(size.max(1), Ordering::Relaxed);
+}
+
+/// Left edge of the left paddle. Drawing and collisions both go through this and
+/// `right_paddle_x`, so the paddles are hit exactly where they are drawn.
+pub fn left_paddle_x() -> usize {
+    PADDLE_MARGIN
+}
+
+/// Left edge of a right paddle `paddle_width` wide on a screen `screen_width` wide.
+pub fn right_paddle_x(screen_width: usize, paddle_width: usize) -> usize {
+    screen_width.saturating_sub(paddle_width + PADDLE_MARGIN)
+}
+
+/// Left edge of the paddle on `side`, where `ScreenWriter::draw_pong_game` draws it.
+fn paddle_x(side: Side) -> usize {
+    match side {
+        Side::Left => left_paddle_x(),
+        Side::Right => right_paddle_x(screenwriter().width(), paddle_width(Side::Right)),
+    }
+}
+
+/// Current width of the paddle on `side`.
+fn paddle_width(side: Side) -> usize {
+    game_state().paddle_state(side).width
+}
+
+/// Current height of the paddle on `side`.
+fn paddle_height(side: Side) -> usize {
+    game_state().paddle_state(side).height
+}
+
+/// Moves the paddle on `side` whose top edge is at `pos` by `delta` pixels, keeping the whole
+/// paddle on screen.
+fn move_paddle(side: Side, pos: usize, delta: isize) -> usize {
+    let reach = paddle_reach(side);
+    pos.saturating_add_signed(delta).clamp(*reach.start(), *reach.end())
+}
+
+/// Where the top edge of the paddle on `side` can be with the whole paddle on screen.
+fn paddle_reach(side: Side) -> RangeInclusive<usize> {
+    BORDER_THICKNESS..=screenwriter().height() - BORDER_THICKNESS - paddle_height(side)
+}
+
+/// Spin a ball picks up from `paddle`, moving as its `direction` and `hold` say, see
+/// `hold_paddle`. A paddle sweeping down curves the ball downward and vice versa.
+fn paddle_spin(paddle: &PaddleState) -> f32 {
+    if paddle.hold == 0 {
+        return 0.0;
+    }
+    paddle.direction as f32 * PADDLE_SPIN
+}
+
+/// Counts a tick and, whenever the real-time clock moves on to a new second, shows how many
+/// ticks ran during the last second in the top-left corner and logs it with the timer rate
+/// (if enabled).
+fn update_fps_counter() {
+    let ticks = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    let second = rtc::seconds() as u32;
+    if LAST_RTC_SECOND.swap(second, Ordering::Relaxed) == second {
+        return;
+    }
+    let fps = ticks - TICKS_AT_LAST_SECOND.swap(ticks, Ordering::Relaxed);
+    if SHOW_FPS.load(Ordering::Relaxed) {
+        let timer_rate = TIMER_RATE.load(Ordering::Relaxed);
+        log_event(format_args!("fps fps={fps} timer_hz={timer_rate}"));
+        clear_fps_counter();
+        screenwriter().set_position(0, 0);
+        write!(screenwriter(), "FPS: {fps}").unwrap();
+    }
+}
+
+fn clear_fps_counter() {
+    let width = screenwriter().text_width(FPS_TEXT_SAMPLE);
+    let height = screenwriter().line_height();
+    screenwriter().clear_rect(0, 0, width, height);
+}
+
+/// Shows the framebuffer test pattern from `ScreenWriter::draw_calibration` instead of the
+/// menu, or goes back to the menu.
+fn toggle_calibration() {
+    if CALIBRATING.fetch_xor(true, Ordering::Relaxed) {
+        screenwriter().clear();
+        screenwriter().draw_border(); // the menu draws itself on the next tick
+    } else {
+        screenwriter().draw_calibration();
+        screenwriter().present();
+    }
+    MENU_IDLE_TICKS.store(0, Ordering::Relaxed);
+}
+
+/// Shows or hides the FPS counter. It appears with the next full second.
+fn toggle_fps_counter() {
+    if SHOW_FPS.fetch_xor(true, Ordering::Relaxed) {
+        clear_fps_counter();
+    }
+}
+
+/// Space: moves on to whatever comes next. Starts a match from the menu, a rematch from the
+/// game-over screen, resumes a paused match, and skips the rest of a serve countdown.
+fn confirm() {
+    match game_state().phase {
+        STATE_MENU | STATE_ENDED => begin_match(),
+        STATE_PAUSED => toggle_pause(),
+        _ => {
+            // Release the ball on the next ball frame, which also clears the countdown digit
+            let countdown = &mut game_state().serve_countdown;
+            *countdown = (*countdown).min(1);
+        }
+    }
+}
+
+/// Switches between playing and paused. Resuming repaints the court over the "PAUSED" text.
+fn toggle_pause() {
+    if game_state().phase == STATE_PAUSED {
+        redraw_all();
+        game_state().phase = STATE_PLAYING;
+    } else {
+        game_state().phase = STATE_PAUSED;
+    }
+}
+
+/// Changes the number of points needed to win, keeping it within
+/// `MIN_WIN_SCORE..=MAX_WIN_SCORE`.
+fn adjust_win_score(delta: i32) {
+    let _ = WIN_SCORE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |score| {
+        Some((score + delta).clamp(MIN_WIN_SCORE, MAX_WIN_SCORE))
+    });
+}
+
+/// Applies the latest command of the remote player to the right paddle, which then moves like
+/// a held key, see `remote::poll`.
+fn poll_remote_player() {
+    match remote::poll() {
+        Some(0) => game_state().right_paddle_state.hold = 0,
+        Some(direction) => hold_paddle(Side::Right, direction),
+        None => {}
+    }
+}
+
+/// Moves the computer-controlled paddle on `side` toward the ball's vertical center, see
+/// `ai_move`.
+fn move_ai_paddle(side: Side) {
+    let reach = paddle_reach(side);
+    ai_move(game_state(), side, difficulty(), match_tick(), ball_size(), reach, rng());
+}
+
+/// Moves the paddle on `side` of `state` for the computer on match tick `tick`, toward the
+/// vertical center of balls `ball_size` pixels large, keeping its top edge within `reach`. It
+/// moves at most the difficulty's `ai_max_speed` pixels per tick so it can be beaten. The paddle
+/// only looks at the ball every `ai_reaction_ticks` ticks, and only at a ball heading toward it.
+/// On `ai_mistake_per_mille` of those looks it aims off or stands still for `AI_FREEZE_TICKS`
+/// ticks. Reads no globals, so the computer can play `game::step` in a loop.
+fn ai_move(
+    state: &mut GameState,
+    side: Side,
+    difficulty: &Difficulty,
+    tick: u64,
+    ball_size: usize,
+    reach: RangeInclusive<usize>,
+    rng: &mut Rng,
+) {
+    let frozen = &mut state.paddle_state(side).ai_frozen;
+    if *frozen > 0 {
+        *frozen -= 1;
+        return;
+    }
+    let height = state.paddle_state(side).height;
+    if tick % difficulty.ai_reaction_ticks == 0 {
+        // Follow the approaching ball closest to the paddle
+        let approaching = state.balls().iter().filter(|ball| match side {
+            Side::Left => ball.velocity_x < 0.0,
+            Side::Right => ball.velocity_x > 0.0,
+        });
+        let closest = match side {
+            Side::Left => approaching.min_by(|a, b| a.x.total_cmp(&b.x)),
+            Side::Right => approaching.max_by(|a, b| a.x.total_cmp(&b.x)),
+        };
+        if let Some(ball) = closest {
+            let center = (ball.pixel_y() + ball_size / 2).saturating_sub(height / 2);
+            let center = center.clamp(*reach.start(), *reach.end());
+            let mistakes = difficulty.ai_mistake_per_mille;
+            // A miss is up to a paddle height off
+            let (target, hesitate) = ai_look(rng, center, &reach, height, mistakes);
+            let paddle = state.paddle_state(side);
+            paddle.ai_target = target;
+            if hesitate {
+                paddle.ai_frozen = AI_FREEZE_TICKS;
             }
-            DecodedKey::Unicode(c) if c == 'S' || c == 's' => {
-                if PADDLE_LEFT + 75 < screenwriter().height() {
-                    PADDLE_LEFT += 25;
+        }
+    }
+
+    let target = state.paddle_state(side).ai_target;
+    let max_speed = difficulty.ai_max_speed;
+    let paddle = state.paddle(side);
+    let delta = (target as isize - *paddle as isize).clamp(-max_speed, max_speed);
+    *paddle = paddle.saturating_add_signed(delta).clamp(*reach.start(), *reach.end());
+}
+
+/// Where the computer heads a paddle after a look at a ball it would meet with the paddle's top
+/// edge at `center`, and whether it hesitates first. On `mistake_per_mille` of looks it makes a
+/// mistake like a person now and then: it aims 1 to `miss` pixels above or below, staying
+/// within `reach` where the paddle can go, or hesitates.
+fn ai_look(
+    rng: &mut Rng,
+    center: usize,
+    reach: &RangeInclusive<usize>,
+    miss: usize,
+    mistake_per_mille: u32,
+) -> (usize, bool) {
+    if rng.range(0, 1000) >= mistake_per_mille {
+        return (center, false);
+    }
+    if rng.range(0, 2) == 0 {
+        let off = rng.range(1, miss as u32 + 1) as usize;
+        let above = center.checked_sub(off).filter(|above| reach.contains(above));
+        let below = center + off;
+        // Whichever way there is room, at random if both
+        let target = match above {
+            Some(above) if !reach.contains(&below) || rng.range(0, 2) == 0 => above,
+            _ => below,
+        };
+        (target, false)
+    } else {
+        (center, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn win_by_two_goes_on_until_a_two_point_lead() {
+        assert!(wins_set(11, 9, 11, true));
+        assert!(!wins_set(11, 10, 11, true), "one point ahead at the win score");
+        assert!(!wins_set(12, 11, 11, true), "one point ahead past it");
+        assert!(wins_set(13, 11, 11, true));
+        assert!(!wins_set(10, 8, 11, true), "two ahead short of the win score");
+        assert!(wins_set(11, 10, 11, false), "any lead wins without the option");
+    }
+
+    #[test]
+    fn deuce_is_shown_once_both_are_a_point_short() {
+        assert_eq!(deuce_text(10, 9, 11), None);
+        assert_eq!(deuce_text(10, 10, 11), Some("Deuce"));
+        assert_eq!(deuce_text(11, 10, 11), Some("Advantage left"));
+        assert_eq!(deuce_text(12, 13, 11), Some("Advantage right"));
+    }
+
+    #[test]
+    fn computer_makes_mistakes_at_about_the_difficulty_rate() {
+        let mut rng = Rng::new(1);
+        for difficulty in &DIFFICULTIES {
+            let per_mille = difficulty.ai_mistake_per_mille;
+            let looks = 200_000;
+            let mistakes = (0..looks)
+                .filter(|_| ai_look(&mut rng, 300, &(2..=418), 60, per_mille) != (300, false))
+                .count() as u32;
+            let expected = looks * per_mille / 1000;
+            assert!(
+                mistakes.abs_diff(expected) <= expected / 10 + 30,
+                "{} made {mistakes} mistakes, expected about {expected}",
+                difficulty.name,
+            );
+        }
+    }
+    #[test]
+    fn computer_aims_off_by_at_least_a_pixel_within_reach() {
+        let mut rng = Rng::new(3);
+        let reach = 2..=418;
+        for center in [2, 30, 200, 390, 418] {
+            for _ in 0..1000 {
+                let (target, hesitate) = ai_look(&mut rng, center, &reach, 60, 1000);
+                if !hesitate {
+                    assert!(target.abs_diff(center) >= 1, "aimed at {center} itself");
+                    assert!(target.abs_diff(center) <= 60);
+                    assert!(reach.contains(&target), "aimed at {target} from {center}");
                 }
             }
-            DecodedKey::RawKey(KeyCode::ArrowUp) => {
-                if PADDLE_RIGHT > 25 {
-                    PADDLE_RIGHT -= 25;
-                }
+        }
+    }
+
+    /// Plays `approaches` balls at a computer paddle on the right, from a wall on the left
+    /// returning every ball, and returns how many the computer missed.
+    fn computer_misses(difficulty: &Difficulty, approaches: u32, rng: &mut Rng) -> u32 {
+        let (width, height) = (640, 480);
+        let reach = BORDER_THICKNESS..=height - BORDER_THICKNESS - PADDLE_HEIGHT;
+        let wall = game::Paddle {
+            x: PADDLE_MARGIN as f32,
+            y: 0.0,
+            width: PADDLE_WIDTH as f32,
+            height: height as f32,
+            spin: 0.0,
+        };
+        let mut court = Court {
+            width: width as f32,
+            height: height as f32,
+            border: BORDER_THICKNESS as f32,
+            ball_size: BALL_SIZE as f32,
+            max_speed: difficulty.max_speed,
+            time_scale: 1.0,
+            gravity: 0.0,
+            left: wall,
+            right: game::Paddle {
+                x: right_paddle_x(width, PADDLE_WIDTH) as f32,
+                height: PADDLE_HEIGHT as f32,
+                ..wall
+            },
+        };
+        let mut state = GameState::new(STATE_PLAYING);
+        let (mut misses, mut hits) = (0, 0);
+        for tick in 0.. {
+            if misses + hits == approaches {
+                break;
             }
-            DecodedKey::RawKey(KeyCode::ArrowDown) => {
-                if PADDLE_RIGHT + 75 < screenwriter().height() {
-                    PADDLE_RIGHT += 25;
+            if state.active_balls == 0 {
+                let (x, y) = ((width - BALL_SIZE) / 2, (height - BALL_SIZE) / 2);
+                state.balls[0] = Ball::new(x as f32, y as f32, 0.0, 0.0);
+                let (speed_x, speed_y) = (difficulty.serve_speed_x, difficulty.serve_speed_y);
+                state.balls[0].serve(1, speed_x, speed_y, rng);
+                state.active_balls = 1;
+            }
+            ai_move(&mut state, Side::Right, difficulty, tick, BALL_SIZE, reach.clone(), rng);
+            if tick % SPEED_NORMAL as u64 != 0 {
+                continue; // the ball moves every other tick, as at normal speed
+            }
+            court.right.y = state.paddle_right as f32;
+            for event in game::step(&mut state, &court).into_iter().flatten() {
+                match event {
+                    Event::Hit(Side::Right) => hits += 1,
+                    Event::Score(Side::Left) => misses += 1,
+                    _ => {}
                 }
             }
-            _ => {}
         }
+        misses
     }
 
-    screenwriter().draw_pong_game();
+    #[test]
+    fn computer_misses_about_as_often_as_the_difficulty_says() {
+        let mut rng = Rng::new(1);
+        let approaches = 20_000;
+        for difficulty in &DIFFICULTIES {
+            // Several looks per ball, but most mistakes are made up for before it arrives
+            let per_mille = difficulty.ai_mistake_per_mille;
+            let expected = approaches * per_mille / 1000;
+            let misses = computer_misses(difficulty, approaches, &mut rng);
+            assert!(
+                (expected / 2..=expected * 2).contains(&misses),
+                "{} missed {misses} of {approaches} balls, expected about {expected}",
+                difficulty.name,
+            );
+
+            let flawless = Difficulty { ai_mistake_per_mille: 0, ..*difficulty };
+            let misses = computer_misses(&flawless, approaches, &mut rng);
+            assert!(misses <= approaches / 1000, "{} missed {misses} balls", difficulty.name);
+        }
+    }
 }
@@ -0,0 +1,23 @@
+use kernel::serial;
+
+/// Commands a remote player sends over serial, one byte per frame
+pub const UP: u8 = b'u';
+pub const DOWN: u8 = b'd';
+pub const NONE: u8 = b'n';
+
+/// Reads everything the remote player has sent since the last call and returns the most recent
+/// command as a direction: -1 up, 1 down, 0 none. Returns `None` if no command arrived.
+/// Other bytes are ignored.
+pub fn poll() -> Option<i32> {
+    let port = serial();
+    let mut direction = None;
+    while let Ok(byte) = port.try_receive() {
+        direction = match byte {
+            UP => Some(-1),
+            DOWN => Some(1),
+            NONE => Some(0),
+            _ => direction,
+        };
+    }
+    direction
+}
@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+use core::fmt::Write;
+use kernel::{RacyCell, serial};
+use pc_keyboard::DecodedKey;
+
+/// Key presses kept per match; later ones are not recorded, so a long match replays only up to
+/// that point correctly
+const MAX_KEYS: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    Recording,
+    Playing { next: usize }, // index of the next key to hand out
+}
+
+/// The keys pressed during a match, each with the match tick it arrived before, and the seed
+/// the random number generator was started from
+struct Replay {
+    mode: Mode,
+    seed: Option<u64>, // none until a match has been recorded
+    keys: Vec<(u64, DecodedKey)>,
+}
+
+static REPLAY: RacyCell<Replay> = RacyCell::new(Replay {
+    mode: Mode::Idle,
+    seed: None,
+    keys: Vec::new(),
+});
+
+fn replay() -> &'static mut Replay {
+    unsafe { REPLAY.get_mut() }
+}
+
+/// Starts recording a match played with the random number generator seeded with `seed`,
+/// replacing the previous recording.
+pub fn start_recording(seed: u64) {
+    let replay = replay();
+    replay.mode = Mode::Recording;
+    replay.seed = Some(seed);
+    replay.keys.clear();
+}
+
+/// Records `key`, pressed before match tick `tick`, if a match is being recorded.
+pub fn record(tick: u64, key: DecodedKey) {
+    let replay = replay();
+    if replay.mode == Mode::Recording && replay.keys.len() < MAX_KEYS {
+        replay.keys.push((tick, key));
+    }
+}
+
+/// Starts playing back the last recorded match and returns the seed it was played with, or
+/// `None` if nothing was recorded yet.
+pub fn start_playback() -> Option<u64> {
+    let replay = replay();
+    let seed = replay.seed?;
+    replay.mode = Mode::Playing { next: 0 };
+    Some(seed)
+}
+
+/// Whether a recorded match is being played back.
+pub fn is_playing() -> bool {
+    matches!(replay().mode, Mode::Playing { .. })
+}
+
+/// The next recorded key due at match tick `tick`, if any. Call until it returns `None` at the
+/// start of every tick of a playback.
+pub fn next_key(tick: u64) -> Option<DecodedKey> {
+    let replay = replay();
+    let Mode::Playing { next } = replay.mode else {
+        return None;
+    };
+    let &(_, key) = replay.keys.get(next).filter(|(at, _)| *at <= tick)?;
+    replay.mode = Mode::Playing { next: next + 1 };
+    Some(key)
+}
+
+/// Ends the recording or playback, if any. A finished recording is sent over serial as a
+/// `REPLAY seed=<hex> keys=<count>` line, one `REPLAY <tick> <key>` line per key, and
+/// `REPLAY END`, and is kept for `start_playback`.
+pub fn finish() {
+    let replay = replay();
+    if replay.mode == Mode::Recording {
+        let serial = serial();
+        let seed = replay.seed.unwrap_or(0);
+        let _ = writeln!(serial, "REPLAY seed={seed:016x} keys={}", replay.keys.len());
+        for (tick, key) in &replay.keys {
+            let _ = writeln!(serial, "REPLAY {tick} {key:?}");
+        }
+        let _ = writeln!(serial, "REPLAY END");
+    }
+    replay.mode = Mode::Idle;
+}
@@ -0,0 +1,79 @@
+use kernel::RacyCell;
+
+static RNG: RacyCell<Option<Rng>> = RacyCell::new(None);
+
+/// The generator set up by `init`.
+pub fn rng() -> &'static mut Rng {
+    unsafe { RNG.get_mut() }.as_mut().unwrap()
+}
+
+/// Sets up the generator returned by `rng`.
+pub fn init(seed: u64) {
+    *unsafe { RNG.get_mut() } = Some(Rng::new(seed));
+}
+
+/// Used instead of a zero seed, which xorshift would never leave
+const FALLBACK_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// xorshift64 pseudo-random number generator, https://en.wikipedia.org/wiki/Xorshift
+///
+/// Good enough for gameplay, not for anything secret. The same seed always gives the same
+/// sequence of numbers.
+pub struct Rng {
+    state: u64, // never zero
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 { FALLBACK_SEED } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        (x >> 32) as u32 // the high bits are the better mixed ones
+    }
+
+    /// Returns a number in `lo..hi`, or `lo` if that range is empty.
+    pub fn range(&mut self, lo: u32, hi: u32) -> u32 {
+        if hi <= lo {
+            return lo;
+        }
+        lo + self.next_u32() % (hi - lo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_the_same_sequence() {
+        let (mut a, mut b) = (Rng::new(1234), Rng::new(1234));
+        for _ in 0..1000 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn sequence_matches_xorshift64() {
+        let mut rng = Rng::new(42);
+        let first: [u32; 4] = core::array::from_fn(|_| rng.next_u32());
+        assert_eq!(first, [0x0000_000A, 0xA00A_AAFD, 0x8B13_399C, 0x283B_88FE]);
+    }
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut rng = Rng::new(7);
+        for (lo, hi) in [(0, 1), (3, 10), (100, 101), (5, 1 << 20)] {
+            for _ in 0..1000 {
+                assert!((lo..hi).contains(&rng.range(lo, hi)), "{lo}..{hi}");
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use x86_64::instructions::port::Port;
+
+// https://wiki.osdev.org/CMOS
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+const RTC_SECONDS: u8 = 0x00;
+
+/// Reads the seconds register of the real-time clock. The raw value (BCD or binary, depending on
+/// the firmware) is returned as is, which is enough to notice when a wall-clock second passes.
+pub fn seconds() -> u8 {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(RTC_SECONDS);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
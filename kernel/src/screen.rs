@@ -1,62 +1,308 @@
 // Original code from rust-osdev/bootloader crate https://github.com/rust-osdev/bootloader
 
-use core::{fmt, ptr};
-use noto_sans_mono_bitmap::{FontWeight, get_raster, RasterizedChar};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::fmt;
+use noto_sans_mono_bitmap::{FontWeight, get_raster, get_raster_width, RasterizedChar};
 use bootloader_api::info::{FrameBuffer, FrameBufferInfo, PixelFormat};
 use noto_sans_mono_bitmap::RasterHeight::Size16;
-use kernel::RacyCell;
+use kernel::{RacyCell, serial};
 
 static WRITER: RacyCell<Option<ScreenWriter>> = RacyCell::new(None);
 pub struct Writer;
 
 impl fmt::Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let writer = unsafe { WRITER.get_mut() }.as_mut().unwrap();
-        writer.write_str(s)
+        screenwriter().write_str(s)
     }
 }
 
+/// The screen writer set up by `init`.
+///
+/// ## Panics
+/// If `init` has not been called yet. Use `try_screenwriter` where that can happen.
 pub fn screenwriter() -> &'static mut ScreenWriter {
-    let writer = unsafe { WRITER.get_mut() }.as_mut().unwrap();
-    writer
+    try_screenwriter().expect("screen not initialized, call screen::init first")
+}
+
+/// The screen writer set up by `init`, or `None` before that.
+pub fn try_screenwriter() -> Option<&'static mut ScreenWriter> {
+    unsafe { WRITER.get_mut() }.as_mut()
 }
 
 
 pub fn init(buffer: &'static mut FrameBuffer) {
     let info = buffer.info();
     let framebuffer = buffer.buffer_mut();
-    let writer = ScreenWriter::new(framebuffer, info);
+    let mut writer = ScreenWriter::new(framebuffer, info);
+    writer.set_text_scale((info.height / TEXT_SCALE_HEIGHT).max(1));
     *unsafe { WRITER.get_mut() } = Some(writer);
 }
 
 /// Additional vertical space between lines
 const LINE_SPACING: usize = 0;
 
+/// Vertical distance between the tops of two lines of text
+pub const LINE_HEIGHT: usize = Size16 as usize + LINE_SPACING;
+
+/// Screen height per step of text scale, so text is twice as large at 1080p as at 720p
+const TEXT_SCALE_HEIGHT: usize = 540;
+
+/// Color of text at full intensity
+const TEXT_COLOR: Color = (63, 255, 127);
+
+/// Lit segments for each digit 0-9. Bits 0-6 are top, upper right, lower right, bottom,
+/// lower left, upper left and middle.
+const SEGMENTS: [u8; 10] = [
+    0b011_1111, // 0
+    0b000_0110, // 1
+    0b101_1011, // 2
+    0b100_1111, // 3
+    0b110_0110, // 4
+    0b110_1101, // 5
+    0b111_1101, // 6
+    0b000_0111, // 7
+    0b111_1111, // 8
+    0b110_1111, // 9
+];
+
+/// Thickness of the court outline drawn by `draw_border`
+pub const BORDER_THICKNESS: usize = 2;
+
+/// An (r, g, b) color
+pub type Color = (u8, u8, u8);
+
+/// Colors used to draw the game.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    pub paddle: Color, // the scores and power-ups
+    pub left_paddle: Color,
+    pub right_paddle: Color,
+    pub ball: Color,
+    pub mid_line: Color,
+    pub background: Color,
+}
+
+/// White on black, like the original
+pub const CLASSIC: Theme = Theme {
+    name: "Classic",
+    paddle: (255, 255, 255),
+    left_paddle: (255, 255, 255),
+    right_paddle: (255, 255, 255),
+    ball: (0xff, 0xff, 0x00),
+    mid_line: (255, 255, 255),
+    background: (0, 0, 0),
+};
+
+/// Monochrome green CRT
+pub const GREEN_PHOSPHOR: Theme = Theme {
+    name: "Green phosphor",
+    paddle: (0x33, 0xff, 0x33),
+    left_paddle: (0x33, 0xff, 0x33),
+    right_paddle: (0x33, 0xff, 0x33),
+    ball: (0x99, 0xff, 0x99),
+    mid_line: (0x22, 0xaa, 0x22),
+    background: (0x00, 0x14, 0x00),
+};
+
+/// Monochrome amber CRT
+pub const AMBER: Theme = Theme {
+    name: "Amber",
+    paddle: (0xff, 0xb0, 0x00),
+    left_paddle: (0xff, 0xb0, 0x00),
+    right_paddle: (0xff, 0xb0, 0x00),
+    ball: (0xff, 0xd8, 0x60),
+    mid_line: (0xb0, 0x78, 0x00),
+    background: (0x18, 0x0c, 0x00),
+};
+
+/// Blue left paddle against red right paddle, so the sides are told apart at a glance
+pub const RED_VS_BLUE: Theme = Theme {
+    name: "Red vs blue",
+    paddle: (255, 255, 255),
+    left_paddle: (0x40, 0x80, 0xff),
+    right_paddle: (0xff, 0x40, 0x40),
+    ball: (255, 255, 255),
+    mid_line: (0x80, 0x80, 0x80),
+    background: (0, 0, 0),
+};
+
+/// The themes that can be picked from the menu, in order
+pub const THEMES: [Theme; 4] = [CLASSIC, GREEN_PHOSPHOR, AMBER, RED_VS_BLUE];
+
+/// How the balls are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BallShape {
+    Round,  // a circle inscribed in the ball's square
+    Square, // the whole square
+}
+
+/// Length of each arm of the calibration crosshairs, in pixels
+const CROSSHAIR_ARM: usize = 20;
+
+/// How far a flash lifts the background toward the mid-line color, as 1 / FLASH_STRENGTH
+const FLASH_STRENGTH: usize = 6;
+
+/// Number of ball frames remembered for the trail, including the current one
+const TRAIL_LENGTH: usize = 4;
+
+const MID_LINE_WIDTH: usize = 5;
+const MID_LINE_DASHES: usize = 20; // at most 32, one bit each in `mid_line_dirty`
+const ALL_DASHES: u32 = (1 << MID_LINE_DASHES) - 1;
+
+/// Where and how a ball was drawn, so exactly those pixels can be cleared again even after the
+/// ball's size or style has changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BallFootprint {
+    x: usize,
+    y: usize,
+    size: usize,
+    shape: BallShape,
+}
+
+/// The balls drawn in one ball frame
+type BallPositions = [Option<BallFootprint>; crate::MAX_BALLS];
+
+/// Area covered by a drawn paddle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PaddleRect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl PaddleRect {
+    /// Whether the paddle overlaps the `size` × `size` square at (x, y).
+    fn overlaps(&self, x: usize, y: usize, size: usize) -> bool {
+        x < self.x + self.width && self.x < x + size && y < self.y + self.height && self.y < y + size
+    }
+}
+
 pub struct ScreenWriter {
     framebuffer: &'static mut [u8],
+    back_buffer: Vec<u8>, // all drawing goes here until `present` copies it to `framebuffer`
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
-    previous_paddle_left_pos: usize,   // Track previous position of the left paddle
-    previous_paddle_right_pos: usize,  // Track previous position of the right paddle
+    previous_paddle_left_pos: Option<PaddleRect>,  // Where the left paddle was last drawn
+    previous_paddle_right_pos: Option<PaddleRect>, // Where the right paddle was last drawn
+    paddles_dirty: [bool; 2], // left and right paddle partly cleared since they were drawn
+    ball_trail: [BallPositions; TRAIL_LENGTH], // ring buffer of the last few ball frames
+    trail_next: usize,                         // slot in `ball_trail` overwritten next
+    theme: Theme,
+    ball_color: Color, // the ball style's color, tinted by `set_ball_tint`
+    ball_shape: BallShape,
+    ball_style_color: Option<Color>, // used instead of the theme's ball color, see `set_ball_style`
+    text_scale: usize, // every font pixel is drawn as a `text_scale` × `text_scale` block
+    mid_line_dirty: u32, // bit i set: dash i of the mid-line was drawn over since it was drawn
+    high_contrast_text: bool, // see `set_high_contrast_text`
 }
 
 impl ScreenWriter {
-    pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+    /// Creates a writer for `framebuffer` and clears it.
+    ///
+    /// Drawing is double buffered: a back buffer as large as the framebuffer is allocated on the
+    /// heap (about 8 MiB at 1920x1080 with 4 bytes per pixel), so the heap must be initialized
+    /// first and be big enough to hold it.
+    ///
+    /// Pixel formats other than Rgb, Bgr and U8 are not understood; for those a warning is
+    /// logged over serial and pixels are written as raw bytes in red, green, blue order
+    /// (padded to `bytes_per_pixel`), which keeps drawing from ever panicking.
+    pub fn new(framebuffer: &'static mut [u8], mut info: FrameBufferInfo) -> Self {
+        match info.pixel_format {
+            PixelFormat::Rgb | PixelFormat::Bgr | PixelFormat::U8 => {}
+            other => {
+                let _ = writeln!(
+                    serial(),
+                    "WARNING: pixel format {other:?} not supported, writing raw RGB bytes"
+                );
+                info.pixel_format = PixelFormat::Rgb;
+            }
+        }
         let mut logger = Self {
+            back_buffer: vec![0; framebuffer.len()],
             framebuffer,
             info,
             x_pos: 0,
             y_pos: 0,
-            previous_paddle_left_pos: 0,  // Initializing previous paddle positions
-            previous_paddle_right_pos: 0, // Initializing previous paddle positions
+            previous_paddle_left_pos: None,  // Nothing drawn yet
+            previous_paddle_right_pos: None, // Nothing drawn yet
+            paddles_dirty: [false; 2],
+            ball_trail: [[None; crate::MAX_BALLS]; TRAIL_LENGTH],
+            trail_next: 0,
+            theme: CLASSIC,
+            ball_color: CLASSIC.ball,
+            ball_shape: BallShape::Round,
+            ball_style_color: None,
+            text_scale: 1,
+            mid_line_dirty: ALL_DASHES,
+            high_contrast_text: false,
         };
         logger.clear();
         logger
     }
 
+    /// Creates a writer drawing into a `width` × `height` buffer on the heap instead of a real
+    /// framebuffer, so drawing code can be checked through `pixel` without display hardware.
+    /// The buffer is leaked. Only built for tests, which run on the host.
+    #[cfg(test)]
+    pub fn new_in_memory(width: usize, height: usize, pixel_format: PixelFormat) -> Self {
+        let bytes_per_pixel = match pixel_format {
+            PixelFormat::U8 => 1,
+            _ => 4,
+        };
+        let byte_len = width * height * bytes_per_pixel;
+        let info = FrameBufferInfo {
+            byte_len,
+            width,
+            height,
+            pixel_format,
+            bytes_per_pixel,
+            stride: width,
+        };
+        Self::new(vec![0; byte_len].leak(), info)
+    }
+
+    /// The bytes of the pixel at (x, y) as drawn so far, whether presented yet or not.
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let bytes_per_pixel = usize::from(self.info.bytes_per_pixel);
+        let byte_offset = self.byte_offset(x, y);
+        &self.back_buffer[byte_offset..byte_offset + bytes_per_pixel]
+    }
+
+    /// Where the pixel at (x, y) starts in the buffer. The bootloader reports `stride` in
+    /// pixels, not bytes, and pads a row only by whole pixels, so a row starts `stride *
+    /// bytes_per_pixel` bytes after the previous one for 3-byte pixels just as for 4-byte ones.
+    fn byte_offset(&self, x: usize, y: usize) -> usize {
+        (y * usize::from(self.info.stride) + x) * usize::from(self.info.bytes_per_pixel)
+    }
+
+    /// The color of the pixel at (x, y) as drawn so far, the reverse of `draw_pixel`.
+    pub fn read_pixel(&self, x: usize, y: usize) -> Color {
+        let bytes = self.pixel(x, y);
+        match self.info.pixel_format {
+            PixelFormat::Bgr => (bytes[2], bytes[1], bytes[0]),
+            PixelFormat::U8 => (bytes[0], bytes[0], bytes[0]),
+            _ => (bytes[0], bytes[1], bytes[2]),
+        }
+    }
+
+    /// Draws text `scale` times its normal size from now on, each font pixel blown up to a
+    /// `scale` × `scale` block. A scale of 0 is treated as 1.
+    pub fn set_text_scale(&mut self, scale: usize) {
+        self.text_scale = scale.max(1);
+    }
+
+    /// Vertical distance between the tops of two lines of text at the current text scale
+    pub fn line_height(&self) -> usize {
+        LINE_HEIGHT * self.text_scale
+    }
+
     fn newline(&mut self) {
-        self.y_pos += Size16 as usize + LINE_SPACING;
+        self.y_pos += self.line_height();
         self.carriage_return()
     }
 
@@ -64,11 +310,47 @@ impl ScreenWriter {
         self.x_pos = 0;
     }
 
-    /// Erases all text on the screen.
+    /// Erases everything on the screen, filling it with the background color.
     pub fn clear(&mut self) {
         self.x_pos = 0;
         self.y_pos = 0;
-        self.framebuffer.fill(0);
+        let (r, g, b) = self.theme.background;
+        self.fill_rect(0, 0, self.width(), self.height(), r, g, b);
+        self.mid_line_dirty = ALL_DASHES;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Switches to `theme`. Already drawn objects keep their colors until the screen is cleared.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.ball_color = self.ball_style_color.unwrap_or(theme.ball);
+    }
+
+    /// Draws the balls as `shape` in `color`, or the theme's ball color if `None`, from now on.
+    /// Balls already on screen must be cleared first, as they are cleared in the new shape.
+    pub fn set_ball_style(&mut self, shape: BallShape, color: Option<Color>) {
+        self.ball_shape = shape;
+        self.ball_style_color = color;
+        self.ball_color = color.unwrap_or(self.theme.ball);
+    }
+
+    /// Draws text and font digits in plain white without anti-aliased edges if `on`, instead of
+    /// the default tinted, smoothed look. Text already on screen keeps its look until redrawn.
+    pub fn set_high_contrast_text(&mut self, on: bool) {
+        self.high_contrast_text = on;
+    }
+
+    /// Mixes `tint`, if any, half and half into the ball style's color for the balls drawn from
+    /// now on, e.g. to show who hit the ball last.
+    pub fn set_ball_tint(&mut self, tint: Option<Color>) {
+        let color = self.ball_style_color.unwrap_or(self.theme.ball);
+        self.ball_color = match tint {
+            Some(tint) => fade(color, tint, 1, 2),
+            None => color,
+        };
     }
 
     pub fn width(&self) -> usize {
@@ -86,12 +368,10 @@ impl ScreenWriter {
             c => {
                 match get_raster(c, FontWeight::Regular, Size16) {
                     Some(bitmap_char) => {
-                        if self.x_pos + bitmap_char.width() > self.width() {
+                        if self.x_pos + bitmap_char.width() * self.text_scale > self.width() {
                             self.newline();
                         }
-                        if self.y_pos + bitmap_char.height() > self.height() {
-                            self.clear();
-                        }
+                        // Glyphs past the bottom are clipped by `write_pixel`
                         self.write_rendered_char(bitmap_char);
                     },
                     None => {}
@@ -101,272 +381,557 @@ impl ScreenWriter {
     }
 
     fn write_rendered_char(&mut self, rendered_char: RasterizedChar) {
+        let scale = self.text_scale;
         for (y, row) in rendered_char.raster().iter().enumerate() {
             for (x, byte) in row.iter().enumerate() {
-                self.write_pixel(self.x_pos + x, self.y_pos + y, *byte);
+                let (px, py) = (self.x_pos + x * scale, self.y_pos + y * scale);
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        self.write_pixel(px + dx, py + dy, *byte);
+                    }
+                }
             }
         }
-        self.x_pos += rendered_char.width();
+        self.x_pos += rendered_char.width() * scale;
     }
 
+    /// Draws a text pixel of the given `intensity` in `TEXT_COLOR`, blended into the theme's
+    /// background like `draw_digit_font` does, so a glyph's empty cell shows the background.
+    /// Pixels off screen are skipped.
     pub fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
-        let pixel_offset = y * usize::from(self.info.stride) + x;
-        let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [intensity / 4, intensity, intensity / 2, 0],
-            PixelFormat::Bgr => [intensity / 2, intensity, intensity / 4, 0],
-            other => {
-                // set a supported (but invalid) pixel format before panicking to avoid a double
-                // panic; it might not be readable though
-                self.info.pixel_format = PixelFormat::Rgb;
-                panic!("pixel format {:?} not supported in logger", other)
+        if !self.on_screen(x, y) {
+            return;
+        }
+        let (text, intensity) = if self.high_contrast_text {
+            ((255, 255, 255), high_contrast(intensity))
+        } else {
+            match self.info.pixel_format {
+                PixelFormat::U8 => ((255, 255, 255), intensity), // white on grayscale screens
+                _ => (TEXT_COLOR, intensity),
             }
         };
-        let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * usize::from(bytes_per_pixel);
-        self.framebuffer[byte_offset..(byte_offset + usize::from(bytes_per_pixel))]
-            .copy_from_slice(&color[..usize::from(bytes_per_pixel)]);
-        let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]) };
+        let (r, g, b) = fade(self.theme.background, text, intensity as usize, 255);
+        let color = self.pixel_bytes(r, g, b);
+        self.put_pixel(x, y, color);
     }
 
+    /// Sets the pixel at (x, y) to the given color. Pixels off screen are skipped rather than
+    /// panicking, so callers may draw shapes that stick out of the screen.
     pub fn draw_pixel(&mut self, x: usize, y: usize, r: u8, g: u8, b: u8) {
-        let pixel_offset = y * usize::from(self.info.stride) + x;
-        let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [r, g, b, 0],
+        if !self.on_screen(x, y) {
+            return;
+        }
+        let color = self.pixel_bytes(r, g, b);
+        self.put_pixel(x, y, color);
+    }
+
+    /// Whether (x, y) lies within `width` × `height`. Each row is `stride` pixels long, which may
+    /// be more than `width`, so an x past the width would land in the row's padding or, on the
+    /// last row, past the end of the buffer.
+    fn on_screen(&self, x: usize, y: usize) -> bool {
+        x < self.width() && y < self.height()
+    }
+
+    /// The bytes of a pixel of the given color in the framebuffer's pixel format.
+    fn pixel_bytes(&self, r: u8, g: u8, b: u8) -> [u8; 4] {
+        match self.info.pixel_format {
             PixelFormat::Bgr => [b, g, r, 0],
-            other => {
-                // set a supported (but invalid) pixel format before panicking to avoid a double
-                // panic; it might not be readable though
-                self.info.pixel_format = PixelFormat::Rgb;
-                panic!("pixel format {:?} not supported in logger", other)
-            }
-        };
-        let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * usize::from(bytes_per_pixel);
-        self.framebuffer[byte_offset..(byte_offset + usize::from(bytes_per_pixel))]
-            .copy_from_slice(&color[..usize::from(bytes_per_pixel)]);
-        let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]) };
+            PixelFormat::U8 => [luminance(r, g, b), 0, 0, 0],
+            // Rgb, which `new` also selects for formats it does not support
+            _ => [r, g, b, 0],
+        }
     }
 
-    pub fn draw_zero(&mut self, x: usize, y: usize, size: usize) {
-        let thickness = size / 5;
-    
-        // Draw the top horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + t, 255, 255, 255);
+    /// Draws a straight line from (x0, y0) to (x1, y1) using Bresenham's algorithm.
+    /// Points outside the framebuffer are skipped, so the endpoints may lie off screen.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_line(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, r: u8, g: u8, b: u8) {
+        let (mut x, mut y) = (x0 as isize, y0 as isize);
+        let (x1, y1) = (x1 as isize, y1 as isize);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            if (x as usize) < self.width() && (y as usize) < self.height() {
+                self.draw_pixel(x as usize, y as usize, r, g, b);
             }
-        }
-    
-        // Draw the bottom horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + size - thickness + t, 255, 255, 255);
+            if x == x1 && y == y1 {
+                break;
             }
-        }
-    
-        // Draw the left vertical line
-        for dy in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + t, y + dy, 255, 255, 255);
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
             }
-        }
-    
-        // Draw the right vertical line
-        for dy in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + size - thickness + t, y + dy, 255, 255, 255);
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
             }
         }
     }
-    
-    pub fn draw_one(&mut self, x: usize, y: usize, size: usize) {
-        let thickness = size / 5;
-    
-        for dy in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + size / 2 + t, y + dy, 255, 255, 255);
-            }
-        }
+
+    /// Copies the first `bytes_per_pixel` bytes of `color` (at most 4) into the pixel at (x, y),
+    /// which the caller has checked to be on screen.
+    fn put_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        debug_assert!(x < self.width() && y < self.height());
+        let bytes_per_pixel = usize::from(self.info.bytes_per_pixel);
+        let byte_offset = self.byte_offset(x, y);
+        let len = bytes_per_pixel.min(color.len());
+        self.back_buffer[byte_offset..(byte_offset + len)].copy_from_slice(&color[..len]);
     }
-    
-    pub fn draw_two(&mut self, x: usize, y: usize, size: usize) {
+
+    /// Copies everything drawn since the last call to the visible framebuffer in one go, so a
+    /// frame never shows half-erased objects.
+    ///
+    /// No volatile access is needed: the framebuffer is a `'static` slice, which outlives this
+    /// call, so the compiler has to assume the copied bytes are read later and cannot drop the
+    /// copy.
+    pub fn present(&mut self) {
+        self.framebuffer.copy_from_slice(&self.back_buffer);
+    }
+
+    /// Draws a single decimal digit as a seven-segment glyph of `size` × `size` pixels.
+    /// Digits above 9 are ignored.
+    pub fn draw_digit(&mut self, digit: u8, x: usize, y: usize, size: usize) {
+        let Some(segments) = SEGMENTS.get(digit as usize) else {
+            return;
+        };
         let thickness = size / 5;
-    
-        // Top horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + t, 255, 255, 255);
-            }
-        }
+        let half = size / 2;
 
-        // Right vertical line
-        let vertical_height = size / 2;
-        for dy in 0..vertical_height {
-            for dx in 0..thickness {
-                screenwriter().draw_pixel(x + size - thickness + dx, y + dy, 255, 255, 255);
-            }
-        }
-    
-        // Middle horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + size / 2 - thickness / 2 + t, 255, 255, 255);
+        // (x offset, y offset, width, height) of each segment, in SEGMENTS bit order
+        let bars = [
+            (0, 0, size, thickness),                              // top
+            (size - thickness, 0, thickness, half),               // upper right
+            (size - thickness, half, thickness, size - half),     // lower right
+            (0, size - thickness, size, thickness),               // bottom
+            (0, half, thickness, size - half),                    // lower left
+            (0, 0, thickness, half),                              // upper left
+            (0, half - thickness / 2, size, thickness),           // middle
+        ];
+
+        for (bit, (bx, by, width, height)) in bars.iter().enumerate() {
+            if segments & (1 << bit) != 0 {
+                let (r, g, b) = self.theme.paddle;
+                self.fill_rect(x + bx, y + by, *width, *height, r, g, b);
             }
         }
-    
-        // Bottom horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + size - thickness + t, 255, 255, 255);
-            }
+    }
+
+    /// Draws a single decimal digit with the text font, every glyph pixel blown up to
+    /// `scale` × `scale` pixels. The glyph's anti-aliased edges are blended into the background,
+    /// and its whole `font_digit_size` cell is repainted, so nothing has to be cleared first.
+    /// Falls back to the seven-segment `draw_digit` if the font has no glyph for the digit.
+    pub fn draw_digit_font(&mut self, digit: u8, x: usize, y: usize, scale: usize) {
+        if digit > 9 {
+            return;
         }
-    
-        // Draw the bottom-left vertical line
-        for dy in 0..size / 2 {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + t, y + size / 2 + dy, 255, 255, 255);
+        let Some(glyph) = get_raster((b'0' + digit) as char, FontWeight::Regular, Size16) else {
+            self.draw_digit(digit, x, y, Size16 as usize * scale);
+            return;
+        };
+        let background = self.theme.background;
+        let color = if self.high_contrast_text { (255, 255, 255) } else { self.theme.paddle };
+        for (gy, row) in glyph.raster().iter().enumerate() {
+            for (gx, &intensity) in row.iter().enumerate() {
+                let intensity =
+                    if self.high_contrast_text { high_contrast(intensity) } else { intensity };
+                let (r, g, b) = fade(background, color, intensity as usize, 255);
+                self.fill_rect(x + gx * scale, y + gy * scale, scale, scale, r, g, b);
             }
         }
     }
 
-    pub fn draw_three(&mut self, x: usize, y: usize, size: usize) {
-        let thickness = size / 5;
-    
-        // Top horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + t, 255, 255, 255);
-            }
-        }
-    
-        // Middle horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + size / 2 - thickness / 2 + t, 255, 255, 255);
-            }
+    /// Width and height in pixels of a digit drawn by `draw_digit_font` at `scale`.
+    pub fn font_digit_size(scale: usize) -> (usize, usize) {
+        let width = get_raster_width(FontWeight::Regular, Size16);
+        (width * scale, Size16 as usize * scale)
+    }
+
+    pub fn clear_score(&mut self, x: usize, y: usize, size: usize) {
+        self.clear_rect(x, y, size, size);
+    }
+
+    /// Fills the `w` × `h` rectangle with its top-left corner at (x, y).
+    /// The part of the rectangle outside the framebuffer is skipped.
+    ///
+    /// Only the first row is filled pixel by pixel; every other row is a copy of it, which is
+    /// much faster than `draw_pixel` for large areas like paddles and cleared scores.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, r: u8, g: u8, b: u8) {
+        self.touch_mid_line(x, y, w, h);
+        let x_end = x.saturating_add(w).min(self.width());
+        let y_end = y.saturating_add(h).min(self.height());
+        if x >= x_end || y >= y_end {
+            return;
         }
-    
-        // Bottom horizontal line
-        for dx in 0..size {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + dx, y + size - thickness + t, 255, 255, 255);
-            }
+        let color = self.pixel_bytes(r, g, b);
+        let bytes_per_pixel = usize::from(self.info.bytes_per_pixel);
+        let stride = usize::from(self.info.stride) * bytes_per_pixel; // in bytes
+        let len = bytes_per_pixel.min(color.len());
+        let first_row = self.byte_offset(x, y)..self.byte_offset(x_end, y);
+        for pixel in self.back_buffer[first_row.clone()].chunks_exact_mut(bytes_per_pixel) {
+            pixel[..len].copy_from_slice(&color[..len]);
         }
-    
-        // Right vertical line (upper half)
-        for dy in 0..size / 2 {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + size - thickness + t, y + dy, 255, 255, 255);
-            }
+        for py in y + 1..y_end {
+            let row_start = first_row.start + (py - y) * stride;
+            self.back_buffer.copy_within(first_row.clone(), row_start);
         }
-    
-        // Right vertical line (lower half)
-        for dy in 0..size / 2 {
-            for t in 0..thickness {
-                screenwriter().draw_pixel(x + size - thickness + t, y + size / 2 + dy, 255, 255, 255);
+    }
+
+    /// Fills the `w` × `h` rectangle at (x, y) with the background color.
+    pub fn clear_rect(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        let (r, g, b) = self.theme.background;
+        self.fill_rect(x, y, w, h, r, g, b);
+    }
+
+    /// Width in pixels of `text` as rendered by the text writer at the current text scale.
+    pub fn text_width(&self, text: &str) -> usize {
+        let width: usize = text.chars()
+            .filter_map(|c| get_raster(c, FontWeight::Regular, Size16))
+            .map(|glyph| glyph.width())
+            .sum();
+        width * self.text_scale
+    }
+
+    /// Writes `text` horizontally centered on the line starting at `y`. Text wider than the
+    /// screen is wrapped at spaces, each line centered on its own. Returns the `y` of the line
+    /// below the text.
+    pub fn draw_centered_text(&mut self, text: &str, y: usize) -> usize {
+        let mut y = y;
+        let mut rest = text;
+        loop {
+            let line = self.fitting_line(rest);
+            let x = (self.width() / 2).saturating_sub(self.text_width(line) / 2);
+            self.set_position(x, y);
+            let _ = self.write_str(line);
+            y += self.line_height();
+
+            rest = rest[line.len()..].trim_start();
+            if rest.is_empty() {
+                return y;
             }
         }
     }
 
-    pub fn clear_score(&mut self, x: usize, y: usize, size: usize) {
-        let width = size;  // Total pixel width of the digit
-        let height = size; // Total pixel height of the digit
-        for dx in 0..width {
-            for dy in 0..height {
-                self.draw_pixel(x + dx, y + dy, 0, 0, 0); // Clear with black
+    /// The longest start of `text` that ends between words and fits the screen width. A single
+    /// word that is too wide on its own is returned whole.
+    fn fitting_line<'a>(&self, text: &'a str) -> &'a str {
+        if self.text_width(text) <= self.width() {
+            return text;
+        }
+        let mut end = text.find(' ').unwrap_or(text.len());
+        for (index, _) in text.match_indices(' ') {
+            if self.text_width(&text[..index]) > self.width() {
+                break;
             }
+            end = index;
         }
+        &text[..end]
     }
-    
-    
 
     pub fn set_position(&mut self, x: usize, y: usize) {
         self.x_pos = x;
         self.y_pos = y;
     }
 
-    pub fn draw_pong_pad(&mut self, x_pos: usize, y_pos: usize, height: usize, width: usize) {
-        for y in y_pos..(y_pos + height) {
-            for x in x_pos..(x_pos + width) {
-                self.draw_pixel(x, y, 255, 255, 255); // White color for the pad
-            }
-        }
+    pub fn draw_pong_pad(
+        &mut self,
+        x_pos: usize,
+        y_pos: usize,
+        height: usize,
+        width: usize,
+        color: Color,
+    ) {
+        let (r, g, b) = color;
+        self.fill_rect(x_pos, y_pos, width, height, r, g, b);
     }
 
+    /// Redraws each paddle that moved or changed size since the last call, see `draw_paddles`.
     pub fn draw_pong_game(&mut self) {
-        // Define the size of the pads
-        let paddle_width = 10;
-        let paddle_height = 60;
-
-        let paddle_left_pos = unsafe { crate::PADDLE_LEFT };
-        let paddle_right_pos = unsafe { crate::PADDLE_RIGHT };
-
-        let paddle_left_x = 10;
-        let paddle_right_x = self.width() - paddle_width - 10;
-
-        unsafe {
-            // Only clear and redraw if the positions have changed
-            if paddle_left_pos != self.previous_paddle_left_pos {
-                self.clear_pong_pad(paddle_left_x, self.previous_paddle_left_pos, paddle_height, paddle_width);
-                self.draw_pong_pad(paddle_left_x, paddle_left_pos, paddle_height, paddle_width);
-                self.previous_paddle_left_pos = paddle_left_pos;
+        let state = crate::game_state();
+        let (left, right) = (state.left_paddle_state, state.right_paddle_state);
+        let paddle_left = PaddleRect {
+            x: crate::left_paddle_x(),
+            y: state.paddle_left,
+            width: left.width,
+            height: left.height,
+        };
+        let paddle_right = PaddleRect {
+            x: crate::right_paddle_x(self.width(), right.width),
+            y: state.paddle_right,
+            width: right.width,
+            height: right.height,
+        };
+        self.draw_paddles(paddle_left, paddle_right);
+    }
+
+    /// Draws the paddles at `paddle_left` and `paddle_right`, if either moved, changed size or
+    /// had part of it cleared by a ball's trail since the last call. The rectangle it was last
+    /// drawn in is cleared first, with its old size, so a paddle that shrinks or moves leaves
+    /// nothing behind.
+    fn draw_paddles(&mut self, paddle_left: PaddleRect, paddle_right: PaddleRect) {
+        // Only clear and redraw if the paddles have changed or been drawn over
+        let [left_dirty, right_dirty] = self.paddles_dirty;
+        if Some(paddle_left) != self.previous_paddle_left_pos || left_dirty {
+            self.redraw_paddle(self.previous_paddle_left_pos, paddle_left, self.theme.left_paddle);
+            self.previous_paddle_left_pos = Some(paddle_left);
+        }
+        if Some(paddle_right) != self.previous_paddle_right_pos || right_dirty {
+            let color = self.theme.right_paddle;
+            self.redraw_paddle(self.previous_paddle_right_pos, paddle_right, color);
+            self.previous_paddle_right_pos = Some(paddle_right);
+        }
+        self.paddles_dirty = [false; 2];
+    }
+
+    fn redraw_paddle(&mut self, previous: Option<PaddleRect>, current: PaddleRect, color: Color) {
+        if let Some(previous) = previous {
+            self.clear_pong_pad(previous.x, previous.y, previous.height, previous.width);
+        }
+        self.draw_pong_pad(current.x, current.y, current.height, current.width, color);
+    }
+
+    /// Forgets where the paddles were last drawn, so the next `draw_pong_game` draws them
+    /// fresh without clearing a stale rectangle. Call after the screen has been cleared.
+    pub fn reset_paddle_tracking(&mut self) {
+        self.previous_paddle_left_pos = None;
+        self.previous_paddle_right_pos = None;
+    }
+
+    /// Draws the ball in the `size` × `size` square at (x, y), in the shape set by
+    /// `set_ball_style`.
+    pub fn draw_ball(&mut self, x: usize, y: usize, size: usize) {
+        let shape = self.ball_shape;
+        self.draw_footprint(BallFootprint { x, y, size, shape }, self.ball_color);
+    }
+
+    /// Fills the pixels of `ball` with `color`.
+    fn draw_footprint(&mut self, ball: BallFootprint, color: Color) {
+        let BallFootprint { x, y, size, shape } = ball;
+        let (r, g, b) = color;
+        self.touch_mid_line(x, y, size, size);
+        match shape {
+            BallShape::Round => {
+                let radius = size.saturating_sub(1) / 2;
+                self.draw_filled_circle(x + size / 2, y + size / 2, radius, r, g, b);
             }
-            
-            if paddle_right_pos != self.previous_paddle_right_pos {
-                self.clear_pong_pad(paddle_right_x, self.previous_paddle_right_pos, paddle_height, paddle_width);
-                self.draw_pong_pad(paddle_right_x, paddle_right_pos, paddle_height, paddle_width);
-                self.previous_paddle_right_pos = paddle_right_pos;
+            BallShape::Square => self.fill_rect(x, y, size, size, r, g, b),
+        }
+    }
+
+    /// Draws balls at the pixel `positions`, each followed by a trail of progressively dimmer
+    /// copies at the positions passed to the previous few calls. Call once per ball frame
+    /// instead of clearing and drawing the balls: the oldest trail copies are cleared here,
+    /// exactly as they were drawn. Whatever else they covered is left for the caller to draw
+    /// again: the paddles with `draw_pong_game` and the mid-line with `redraw_mid_line`, which
+    /// both know what was cleared.
+    pub fn draw_balls_with_trail(&mut self, positions: &[(usize, usize)], size: usize) {
+        for ball in self.ball_trail[self.trail_next].into_iter().flatten() {
+            self.clear_ball(ball);
+        }
+
+        let shape = self.ball_shape;
+        let mut frame = [None; crate::MAX_BALLS];
+        for (slot, &(x, y)) in frame.iter_mut().zip(positions) {
+            *slot = Some(BallFootprint { x, y, size, shape });
+        }
+        self.ball_trail[self.trail_next] = frame;
+        self.trail_next = (self.trail_next + 1) % TRAIL_LENGTH;
+
+        // Oldest first, so newer copies end up on top
+        for age in (1..TRAIL_LENGTH).rev() {
+            let slot = (self.trail_next + TRAIL_LENGTH - 1 - age) % TRAIL_LENGTH;
+            let brightness = TRAIL_LENGTH - age;
+            let color = fade(self.theme.background, self.ball_color, brightness, TRAIL_LENGTH);
+            for ball in self.ball_trail[slot].into_iter().flatten() {
+                self.draw_footprint(ball, color);
             }
-            
+        }
+        for &(x, y) in positions {
+            self.draw_ball(x, y, size);
         }
     }
 
-    pub fn draw_ball(&mut self, x: usize, y: usize, size: usize) {
-        for dx in 0..size {
-            for dy in 0..size {
-                let px = x + dx;
-                let py = y + dy;
-                if px < self.width() && py < self.height() {
-                    self.draw_pixel(px, py, 0xff, 0xff, 0x00);
+    /// Forgets the ball trail without clearing it. Call after the screen has been cleared.
+    pub fn reset_ball_trail(&mut self) {
+        self.ball_trail = [[None; crate::MAX_BALLS]; TRAIL_LENGTH];
+    }
+
+    /// Fills every pixel within `radius` of (cx, cy). Pixels off screen are skipped.
+    pub fn draw_filled_circle(&mut self, cx: usize, cy: usize, radius: usize, r: u8, g: u8, b: u8) {
+        let radius = radius as isize;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                let px = cx as isize + dx;
+                let py = cy as isize + dy;
+                if px >= 0 && py >= 0 && (px as usize) < self.width() && (py as usize) < self.height() {
+                    self.draw_pixel(px as usize, py as usize, r, g, b);
                 }
             }
         }
     }
 
+    /// Draws the net as `MID_LINE_DASHES` dashes down the middle of the screen. Each dash is as
+    /// tall as the gap after it, so the net looks the same at any resolution.
     pub fn draw_mid_line(&mut self) {
-        let mid_line_width = 5;
-        let mid_line_height = 10;
-        let total_lines = 20;
-        for i in 0..total_lines {
-            let mid_line_x = (self.width() - mid_line_width) / 2;
-            let mid_line_y = i * (self.height() / total_lines);
-            for y in mid_line_y..(mid_line_y + mid_line_height) {
-                for x in mid_line_x..(mid_line_x + mid_line_width) {
-                    self.draw_pixel(x, y, 255, 255, 255); // White color for the mid line
-                }
-            }
+        for i in 0..MID_LINE_DASHES {
+            self.draw_mid_line_dash(i);
         }
+        self.mid_line_dirty = 0;
     }
 
+    /// Draws dash `index` of the mid-line.
+    fn draw_mid_line_dash(&mut self, index: usize) {
+        let period = self.height() / MID_LINE_DASHES; // one dash plus one gap
+        let mid_line_x = self.mid_line_x();
+        let (r, g, b) = self.theme.mid_line;
+        // Never past the bottom, because MID_LINE_DASHES * period <= height
+        self.fill_rect(mid_line_x, index * period, MID_LINE_WIDTH, period / 2, r, g, b);
+    }
 
-    pub fn clear_pong_pad(&mut self, x_pos: usize, y_pos: usize, height: usize, width: usize) {
-        for y in y_pos..(y_pos + height) {
-            for x in x_pos..(x_pos + width) {
-                self.draw_pixel(x, y, 0, 0, 0); // Clear with black
-            }
+    /// Draws again only the dashes of the mid-line that something was drawn or cleared across
+    /// since they were last drawn, such as a ball crossing the court or the rally count below
+    /// the scores. Most frames this is one dash or none instead of all of them, and two balls
+    /// far apart cost two dashes rather than everything between them.
+    pub fn redraw_mid_line(&mut self) {
+        let dirty = self.mid_line_dirty;
+        for i in (0..MID_LINE_DASHES).filter(|i| dirty & (1 << i) != 0) {
+            self.draw_mid_line_dash(i);
+        }
+        self.mid_line_dirty = 0;
+    }
+
+    fn mid_line_x(&self) -> usize {
+        self.width().saturating_sub(MID_LINE_WIDTH) / 2
+    }
+
+    /// Notes that the `width` × `height` rectangle at (x, y) was drawn over, which may have
+    /// hidden part of the mid-line.
+    fn touch_mid_line(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        let mid_line_x = self.mid_line_x();
+        if x >= mid_line_x + MID_LINE_WIDTH || x + width <= mid_line_x {
+            return;
+        }
+        let period = (self.height() / MID_LINE_DASHES).max(1);
+        let last_row = y.saturating_add(height).saturating_sub(1);
+        let last = (last_row / period).min(MID_LINE_DASHES - 1);
+        for i in y / period..=last {
+            self.mid_line_dirty |= 1 << i;
+        }
+    }
+
+
+    /// Clears the screen and draws a test pattern for checking that the whole framebuffer is
+    /// visible: a 1 pixel border on the outermost pixels, crosshairs at the center and in each
+    /// corner, and the framebuffer's reported size and layout as text. A wrong stride shows as
+    /// slanted or broken lines.
+    pub fn draw_calibration(&mut self) {
+        self.clear();
+        let (right, bottom) = (self.width() - 1, self.height() - 1);
+        let (r, g, b) = self.theme.mid_line;
+        self.draw_line(0, 0, right, 0, r, g, b);
+        self.draw_line(0, bottom, right, bottom, r, g, b);
+        self.draw_line(0, 0, 0, bottom, r, g, b);
+        self.draw_line(right, 0, right, bottom, r, g, b);
+        for (x, y) in [(right / 2, bottom / 2), (0, 0), (right, 0), (0, bottom), (right, bottom)] {
+            self.draw_crosshair(x, y);
+        }
+
+        let info = self.info;
+        let mut text = alloc::string::String::new();
+        let _ = write!(
+            text,
+            "{}x{}, stride {}, {} bytes per pixel, {:?}",
+            info.width, info.height, info.stride, info.bytes_per_pixel, info.pixel_format
+        );
+        let y = self.height() / 2 + 2 * self.line_height();
+        let y = self.draw_centered_text(&text, y);
+        self.draw_centered_text("Press F1 to leave", y);
+    }
+
+    /// Draws a crosshair centered on (x, y), its arms cut off at the edges of the screen.
+    fn draw_crosshair(&mut self, x: usize, y: usize) {
+        let (r, g, b) = self.theme.paddle;
+        let left = x.saturating_sub(CROSSHAIR_ARM);
+        let right = (x + CROSSHAIR_ARM).min(self.width() - 1);
+        let top = y.saturating_sub(CROSSHAIR_ARM);
+        let bottom = (y + CROSSHAIR_ARM).min(self.height() - 1);
+        self.draw_line(left, y, right, y, r, g, b);
+        self.draw_line(x, top, x, bottom, r, g, b);
+    }
+
+    /// Outlines the court along the edges of the screen, `BORDER_THICKNESS` pixels thick.
+    pub fn draw_border(&mut self) {
+        let right = self.width().saturating_sub(1);
+        let bottom = self.height().saturating_sub(1);
+        let (r, g, b) = self.theme.mid_line;
+        for i in 0..BORDER_THICKNESS {
+            self.draw_line(i, i, right - i, i, r, g, b); // top
+            self.draw_line(i, bottom - i, right - i, bottom - i, r, g, b); // bottom
+            self.draw_line(i, i, i, bottom - i, r, g, b); // left
+            self.draw_line(right - i, i, right - i, bottom - i, r, g, b); // right
         }
     }
 
-    pub fn clear_ball(&mut self, ball_x: usize, ball_y: usize, ball_size: usize) {
-        for y in ball_y..(ball_y + ball_size) {
-            for x in ball_x..(ball_x + ball_size) {
-                self.draw_pixel(x, y, 0, 0, 0); // Clear with black
+    /// Draws a power-up as a `size` × `size` square in the paddle color, since it grows a
+    /// paddle.
+    pub fn draw_power_up(&mut self, x: usize, y: usize, size: usize) {
+        let (r, g, b) = self.theme.paddle;
+        self.fill_rect(x, y, size, size, r, g, b);
+    }
+
+    /// Lights up the full-height strip `width` pixels wide at `x` a little, for a flash that
+    /// lasts until whatever is drawn over it is cleared.
+    pub fn flash_strip(&mut self, x: usize, width: usize) {
+        let (r, g, b) = fade(self.theme.background, self.theme.mid_line, 1, FLASH_STRENGTH);
+        self.fill_rect(x, 0, width, self.height(), r, g, b);
+    }
+
+    pub fn clear_pong_pad(&mut self, x_pos: usize, y_pos: usize, height: usize, width: usize) {
+        self.clear_rect(x_pos, y_pos, width, height);
+    }
+
+    /// Erases exactly the pixels drawn for `ball`, noting any paddle it erased part of.
+    fn clear_ball(&mut self, ball: BallFootprint) {
+        self.draw_footprint(ball, self.theme.background);
+        let paddles = [self.previous_paddle_left_pos, self.previous_paddle_right_pos];
+        for (dirty, paddle) in self.paddles_dirty.iter_mut().zip(paddles) {
+            if paddle.is_some_and(|paddle| paddle.overlaps(ball.x, ball.y, ball.size)) {
+                *dirty = true;
             }
         }
     }
 
 }
 
+/// Font pixels at least this bright are drawn fully lit in high-contrast mode, the rest not at all
+const HIGH_CONTRAST_THRESHOLD: u8 = 128;
+
+/// `intensity` of a font pixel pushed to fully on or off, see `set_high_contrast_text`.
+fn high_contrast(intensity: u8) -> u8 {
+    if intensity >= HIGH_CONTRAST_THRESHOLD { 255 } else { 0 }
+}
+
+/// The color `numerator / denominator` of the way from `from` to `to`.
+fn fade(from: Color, to: Color, numerator: usize, denominator: usize) -> Color {
+    let mix = |a: u8, b: u8| {
+        ((a as usize * (denominator - numerator) + b as usize * numerator) / denominator) as u8
+    };
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// Grayscale value of an RGB color, for 8-bit framebuffers.
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u16 * 3 + g as u16 * 6 + b as u16) / 10) as u8
+}
+
 unsafe impl Send for ScreenWriter {}
 unsafe impl Sync for ScreenWriter {}
 
@@ -378,3 +943,352 @@ impl fmt::Write for ScreenWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+    use test::Bencher;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 0];
+    const BLACK: [u8; 4] = [0, 0, 0, 0];
+
+    /// A writer drawing into a buffer on the heap laid out as `info` says.
+    fn writer(info: FrameBufferInfo) -> ScreenWriter {
+        ScreenWriter::new(vec![0; info.byte_len].leak(), info)
+    }
+
+    #[test]
+    fn one_lights_only_the_right_bars() {
+        let mut writer = ScreenWriter::new_in_memory(64, 64, PixelFormat::Rgb);
+        writer.draw_digit(1, 10, 10, 20); // bars 4 pixels thick, x 26..30 on the right
+        for y in 10..30 {
+            assert_eq!(writer.pixel(27, y), WHITE, "right bar at y {y}");
+            assert_eq!(writer.pixel(12, y), BLACK, "left side at y {y}");
+        }
+        assert_eq!(writer.pixel(18, 11), BLACK, "top bar");
+        assert_eq!(writer.pixel(18, 28), BLACK, "bottom bar");
+    }
+
+    #[test]
+    fn unknown_format_draws_raw_rgb() {
+        let pixel_format = PixelFormat::Unknown {
+            red_position: 16,
+            green_position: 8,
+            blue_position: 0,
+        };
+        let mut writer = writer(FrameBufferInfo {
+            byte_len: 64 * 32 * 4,
+            width: 64,
+            height: 32,
+            pixel_format,
+            bytes_per_pixel: 4,
+            stride: 64,
+        });
+        writer.draw_pixel(3, 4, 10, 20, 30);
+        writer.draw_centered_text("Hi", 8);
+        writer.present();
+        assert_eq!(writer.pixel(3, 4), [10, 20, 30, 0]);
+    }
+
+    #[test]
+    fn text_wider_than_a_narrow_screen_wraps() {
+        let mut writer = ScreenWriter::new_in_memory(320, 200, PixelFormat::Rgb);
+        let text = "Right Player Wins! Press 'r' or SPACE for a rematch, Esc for the menu";
+        assert!(writer.text_width(text) > writer.width());
+        let below = writer.draw_centered_text(text, 10);
+        assert!(below >= 10 + 2 * writer.line_height(), "not wrapped");
+        assert!(below <= writer.height());
+    }
+
+    #[test]
+    fn text_is_drawn_on_the_theme_background() {
+        let mut writer = ScreenWriter::new_in_memory(64, 32, PixelFormat::Rgb);
+        writer.set_theme(AMBER);
+        writer.clear();
+        writer.set_position(0, 0);
+        writer.write_str(" .").unwrap(); // a blank cell, then one mostly blank
+        let (r, g, b) = AMBER.background;
+        assert_eq!(writer.pixel(2, 2), [r, g, b, 0]);
+        assert_eq!(writer.pixel(10, 2), [r, g, b, 0]);
+    }
+
+    /// Every pixel of the `width` × `height` box at the top left corner of `writer`, as drawn
+    /// by `draw` in high-contrast mode if `high_contrast`, on the `AMBER` background.
+    fn drawn_pixels(
+        high_contrast: bool,
+        (width, height): (usize, usize),
+        draw: impl Fn(&mut ScreenWriter),
+    ) -> Vec<[u8; 4]> {
+        let mut writer = ScreenWriter::new_in_memory(64, 64, PixelFormat::Rgb);
+        writer.set_theme(AMBER);
+        writer.set_high_contrast_text(high_contrast);
+        writer.clear();
+        draw(&mut writer);
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+        pixels.map(|(x, y)| writer.pixel(x, y).try_into().unwrap()).collect()
+    }
+
+    /// Checks that `draw` leaves only background and pure white pixels in high-contrast mode,
+    /// some of each, and without it smoothed edges between the background and `lit`.
+    fn assert_high_contrast(size: (usize, usize), lit: Color, draw: impl Fn(&mut ScreenWriter)) {
+        let (r, g, b) = AMBER.background;
+        let background = [r, g, b, 0];
+        let pixels = drawn_pixels(true, size, &draw);
+        for pixel in &pixels {
+            assert!([background, WHITE].contains(pixel), "partly lit {pixel:?}");
+        }
+        assert!(pixels.contains(&WHITE) && pixels.contains(&background));
+        let (r, g, b) = lit;
+        let smooth = drawn_pixels(false, size, &draw);
+        let edge = smooth.iter().any(|pixel| ![background, [r, g, b, 0]].contains(pixel));
+        assert!(edge, "no partly lit pixels without high contrast");
+    }
+
+    #[test]
+    fn high_contrast_text_is_plain_white_on_the_background() {
+        assert_high_contrast((32, 16), TEXT_COLOR, |writer| {
+            writer.set_position(0, 0);
+            writer.write_str("Ag").unwrap();
+        });
+    }
+
+    #[test]
+    fn high_contrast_digits_are_plain_white_on_the_background() {
+        let (width, height) = ScreenWriter::font_digit_size(2);
+        let draw = |writer: &mut ScreenWriter| writer.draw_digit_font(8, 0, 0, 2);
+        assert_high_contrast((width, height), AMBER.paddle, draw);
+    }
+
+    #[test]
+    fn text_past_the_bottom_is_clipped_without_clearing() {
+        let mut writer = ScreenWriter::new_in_memory(64, 32, PixelFormat::Rgb);
+        writer.draw_pixel(1, 1, 255, 255, 255);
+        writer.set_position(0, 24); // half a line above the bottom
+        writer.write_str("Hi").unwrap();
+        assert_eq!(writer.pixel(1, 1), WHITE, "screen cleared");
+        let lit = (24..32).flat_map(|y| (0..64).map(move |x| (x, y)))
+            .any(|(x, y)| writer.pixel(x, y) != BLACK);
+        assert!(lit, "the top of the text is not drawn");
+    }
+
+    #[test]
+    fn clear_fills_with_the_theme_background() {
+        let mut writer = ScreenWriter::new_in_memory(64, 32, PixelFormat::Bgr);
+        writer.set_theme(GREEN_PHOSPHOR);
+        writer.clear();
+        let (r, g, b) = GREEN_PHOSPHOR.background;
+        for (x, y) in [(0, 0), (63, 0), (0, 31), (63, 31)] {
+            assert_eq!(writer.pixel(x, y), [b, g, r, 0]);
+        }
+    }
+
+    #[test]
+    fn drawing_past_the_right_edge_leaves_row_padding_alone() {
+        let (width, height) = (100, 60);
+        let mut writer = writer(FrameBufferInfo {
+            byte_len: (width + 16) * height * 4,
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel: 4,
+            stride: width + 16,
+        });
+        writer.fill_rect(90, 0, 40, height, 255, 255, 255);
+        writer.draw_digit(8, 95, 5, 20);
+        writer.draw_pong_pad(95, 10, 30, 20, (255, 255, 255));
+        writer.draw_line(0, 59, 150, 59, 255, 255, 255);
+        writer.draw_mid_line();
+        for y in 0..height {
+            assert_eq!(writer.pixel(width - 1, y), WHITE, "last pixel of row {y}");
+            for x in width..width + 16 {
+                assert_eq!(writer.pixel(x, y), BLACK, "padding at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn paddle_that_shrinks_and_grows_leaves_no_ghost() {
+        let mut writer = ScreenWriter::new_in_memory(64, 64, PixelFormat::Rgb);
+        let right = PaddleRect {
+            x: 50,
+            y: 10,
+            width: 4,
+            height: 20,
+        };
+        let sizes = [(20, 6, 40), (20, 20, 10), (14, 2, 60), (20, 20, 24)]; // y, width, height
+        for (y, width, height) in sizes {
+            let left = PaddleRect {
+                x: 4,
+                y,
+                width,
+                height,
+            };
+            writer.draw_paddles(left, right);
+            for py in 0..64 {
+                for px in 0..40 {
+                    let inside = (4..4 + width).contains(&px) && (y..y + height).contains(&py);
+                    let color = if inside { writer.theme.left_paddle } else { CLASSIC.background };
+                    let (r, g, b) = color;
+                    assert_eq!(writer.pixel(px, py), [r, g, b, 0], "at ({px}, {py})");
+                }
+            }
+        }
+    }
+
+    /// How many pixels of `writer` `draw` writes, found by first filling the screen with a
+    /// color nothing else uses.
+    fn pixels_written(writer: &mut ScreenWriter, draw: impl FnOnce(&mut ScreenWriter)) -> usize {
+        const UNUSED: [u8; 4] = [1, 2, 3, 4];
+        for pixel in writer.back_buffer.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&UNUSED);
+        }
+        draw(writer);
+        writer.back_buffer.chunks_exact(4).filter(|&pixel| pixel != UNUSED).count()
+    }
+
+    #[test]
+    fn frames_redraw_only_the_dashes_drawn_over() {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Rgb);
+        // 20 dashes, each 27 rows of 5 pixels
+        assert_eq!(pixels_written(&mut writer, ScreenWriter::draw_mid_line), 2700);
+
+        writer.draw_ball(100, 500, 8); // nowhere near the net
+        assert_eq!(pixels_written(&mut writer, ScreenWriter::redraw_mid_line), 0);
+
+        writer.draw_ball(writer.mid_line_x(), 500, 8); // across dash 9, rows 486 to 512
+        assert_eq!(pixels_written(&mut writer, ScreenWriter::redraw_mid_line), 135);
+    }
+
+    #[bench]
+    fn clear_1080p(bencher: &mut Bencher) {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Bgr);
+        bencher.iter(|| writer.clear());
+    }
+
+    #[bench]
+    fn fill_1080p(bencher: &mut Bencher) {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Bgr);
+        bencher.iter(|| writer.fill_rect(0, 0, 1920, 1080, 1, 2, 3));
+    }
+
+    /// Filling the screen as `fill_rect` did before it copied rows, for comparison.
+    #[bench]
+    fn fill_1080p_pixel_by_pixel(bencher: &mut Bencher) {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Bgr);
+        bencher.iter(|| {
+            for y in 0..1080 {
+                for x in 0..1920 {
+                    writer.draw_pixel(x, y, 1, 2, 3);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn presented_pixels_reach_the_framebuffer() {
+        let mut writer = ScreenWriter::new_in_memory(64, 32, PixelFormat::Rgb);
+        writer.fill_rect(8, 8, 4, 4, 10, 20, 30);
+        assert_eq!(writer.framebuffer[writer.byte_offset(9, 9)], 0, "shown before `present`");
+        writer.present();
+        let offset = writer.byte_offset(9, 9);
+        assert_eq!(writer.framebuffer[offset..offset + 4], [10, 20, 30, 0]);
+    }
+
+    #[bench]
+    fn present_1080p(bencher: &mut Bencher) {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Bgr);
+        bencher.iter(|| writer.present());
+    }
+
+    /// `fill_1080p_pixel_by_pixel` with the volatile read `draw_pixel` used to do after every
+    /// pixel, for comparison.
+    #[bench]
+    fn fill_1080p_pixel_by_pixel_reading_back(bencher: &mut Bencher) {
+        let mut writer = ScreenWriter::new_in_memory(1920, 1080, PixelFormat::Bgr);
+        bencher.iter(|| {
+            for y in 0..1080 {
+                for x in 0..1920 {
+                    writer.draw_pixel(x, y, 1, 2, 3);
+                    let offset = writer.byte_offset(x, y);
+                    let _ = unsafe { core::ptr::read_volatile(&writer.back_buffer[offset]) };
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn paddle_under_a_cleared_trail_is_drawn_again() {
+        let mut writer = ScreenWriter::new_in_memory(200, 100, PixelFormat::Rgb);
+        let left = PaddleRect {
+            x: 10,
+            y: 20,
+            width: 10,
+            height: 60,
+        };
+        let right = PaddleRect { x: 180, ..left };
+        // A ball bouncing off the left paddle's face, overlapping it a little, then leaving
+        let path = [(30, 40), (22, 42), (16, 44), (22, 46), (30, 48), (60, 50)];
+        for &position in path.iter().chain([(100, 50); TRAIL_LENGTH].iter()) {
+            writer.draw_balls_with_trail(&[position], 8);
+            writer.draw_paddles(left, right); // neither paddle moves
+        }
+        let (r, g, b) = writer.theme.left_paddle;
+        for y in 20..80 {
+            for x in 10..20 {
+                assert_eq!(writer.pixel(x, y), [r, g, b, 0], "hole at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn ball_crossing_the_mid_line_leaves_the_net_whole() {
+        let mut writer = ScreenWriter::new_in_memory(200, 100, PixelFormat::Rgb);
+        writer.draw_mid_line();
+        let before = writer.back_buffer.clone();
+        let mid_line_x = writer.mid_line_x();
+        for step in 0..8 {
+            let x = mid_line_x + 2 * step;
+            writer.draw_balls_with_trail(&[(x.saturating_sub(8), 30 + step)], 8);
+            writer.redraw_mid_line();
+        }
+        for _ in 0..TRAIL_LENGTH {
+            writer.draw_balls_with_trail(&[(10, 10)], 8);
+            writer.redraw_mid_line();
+        }
+        let (left, right) = (mid_line_x - 10, mid_line_x + MID_LINE_WIDTH + 10);
+        for y in 0..100 {
+            for x in left..right {
+                let offset = writer.byte_offset(x, y);
+                let expected = &before[offset..offset + 4];
+                assert_eq!(writer.pixel(x, y), expected, "at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn three_byte_pixels_land_on_their_own_bytes() {
+        // Rows of 10 pixels padded to 12, 36 bytes each
+        let mut writer = writer(FrameBufferInfo {
+            byte_len: 12 * 8 * 3,
+            width: 10,
+            height: 8,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 3,
+            stride: 12,
+        });
+        writer.draw_pixel(9, 2, 10, 20, 30);
+        writer.fill_rect(0, 5, 10, 2, 1, 2, 3);
+        let buffer = &writer.back_buffer;
+        assert_eq!(buffer[2 * 36 + 27..2 * 36 + 30], [30, 20, 10]);
+        for row in [5, 6] {
+            let start = row * 36;
+            assert!(buffer[start..start + 30].chunks(3).all(|pixel| pixel == [3, 2, 1]));
+            assert_eq!(buffer[start + 30..start + 36], [0; 6], "padding of row {row}");
+        }
+        let drawn = [2 * 36 + 27..2 * 36 + 30, 5 * 36..5 * 36 + 30, 6 * 36..6 * 36 + 30];
+        let mut untouched = (0..buffer.len()).filter(|i| !drawn.iter().any(|range| range.contains(i)));
+        assert!(untouched.all(|i| buffer[i] == 0));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::screen::try_screenwriter;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use kernel::{RacyCell, serial};
+
+/// Bytes of the screenshot sent per call of `tick`, so it never holds up a frame for long
+/// however wide the screen is. Each takes two hex digits on the wire.
+const BYTES_PER_TICK: usize = 3 * 1024;
+
+/// A copy of the screen being sent over serial
+struct Screenshot {
+    width: usize,
+    rgb: Vec<u8>, // 3 bytes per pixel, row after row
+    sent: usize,  // bytes of `rgb` sent so far
+}
+
+static SCREENSHOT: RacyCell<Option<Screenshot>> = RacyCell::new(None);
+
+/// Copies the screen as it is now and starts sending it over serial, a few KiB per `tick`.
+///
+/// The output is a header line `SCREENSHOT <width> <height> rgb`, then one line per row with
+/// every pixel as six hex digits (red, green, blue), then `SCREENSHOT END`. Nothing else is
+/// written to serial in between as long as others hold back while `in_progress`. Does nothing
+/// while the previous screenshot is still being sent.
+pub fn start() {
+    let screenshot = unsafe { SCREENSHOT.get_mut() };
+    if screenshot.is_some() {
+        return;
+    }
+    let Some(writer) = try_screenwriter() else {
+        return; // nothing drawn yet
+    };
+    let (width, height) = (writer.width(), writer.height());
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = writer.read_pixel(x, y);
+            rgb.extend_from_slice(&[r, g, b]);
+        }
+    }
+    let _ = writeln!(serial(), "SCREENSHOT {width} {height} rgb");
+    *screenshot = Some(Screenshot {
+        width,
+        rgb,
+        sent: 0,
+    });
+}
+
+/// Whether a screenshot is being sent, so other serial output would end up among its rows.
+pub fn in_progress() -> bool {
+    unsafe { SCREENSHOT.get_mut() }.is_some()
+}
+
+/// Sends the next bytes of the screenshot in progress, if any, ending each row's line on the
+/// way. Call once per timer tick.
+pub fn tick() {
+    let slot = unsafe { SCREENSHOT.get_mut() };
+    let Some(screenshot) = slot else {
+        return;
+    };
+    let port = serial();
+    let row_len = screenshot.width * 3;
+    let end = (screenshot.sent + BYTES_PER_TICK).min(screenshot.rgb.len());
+    for (offset, byte) in screenshot.rgb[screenshot.sent..end].iter().enumerate() {
+        let _ = write!(port, "{byte:02x}");
+        if (screenshot.sent + offset + 1) % row_len == 0 {
+            let _ = writeln!(port);
+        }
+    }
+    screenshot.sent = end;
+    if end == screenshot.rgb.len() {
+        let _ = writeln!(port, "SCREENSHOT END");
+        *slot = None; // frees the copy
+    }
+}
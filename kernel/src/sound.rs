@@ -0,0 +1,72 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use x86_64::instructions::port::Port;
+
+// https://wiki.osdev.org/PC_Speaker
+const PIT_FREQUENCY: u32 = 1_193_182;
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const SPEAKER_CONTROL: u16 = 0x61;
+
+/// Ticks left before the current beep is silenced, see `beep_for`
+static BEEP_TICKS_LEFT: AtomicU32 = AtomicU32::new(0);
+
+/// Whether `beep_for` makes any sound; turn it off to keep the game quiet.
+pub static SOUND_ON: AtomicBool = AtomicBool::new(true);
+
+/// Starts the PC speaker sounding a square wave of `freq_hz`. It keeps playing until `silence`.
+pub fn beep(freq_hz: u32) {
+    let divisor = PIT_FREQUENCY / freq_hz.max(19); // the divisor has to fit in 16 bits
+    unsafe {
+        // channel 2, lobyte/hibyte access, square wave generator
+        Port::<u8>::new(PIT_COMMAND).write(0xB6);
+        Port::<u8>::new(PIT_CHANNEL_2).write(divisor as u8);
+        Port::<u8>::new(PIT_CHANNEL_2).write((divisor >> 8) as u8);
+
+        // connect the speaker to channel 2 and enable it
+        let mut control = Port::<u8>::new(SPEAKER_CONTROL);
+        let value = control.read();
+        if value & 0b11 != 0b11 {
+            control.write(value | 0b11);
+        }
+    }
+}
+
+/// Stops the PC speaker.
+pub fn silence() {
+    unsafe {
+        let mut control = Port::<u8>::new(SPEAKER_CONTROL);
+        let value = control.read();
+        control.write(value & !0b11);
+    }
+}
+
+/// Beeps at `freq_hz` for `ticks` calls of `tick`, without blocking the caller. Does nothing
+/// while `SOUND_ON` is off.
+pub fn beep_for(freq_hz: u32, ticks: u32) {
+    if !SOUND_ON.load(Ordering::Relaxed) {
+        return;
+    }
+    beep(freq_hz);
+    BEEP_TICKS_LEFT.store(ticks, Ordering::Relaxed);
+}
+
+/// Turns sound off, cutting short any beep still playing, or back on.
+pub fn toggle_sound() {
+    if SOUND_ON.fetch_xor(true, Ordering::Relaxed) {
+        silence();
+        BEEP_TICKS_LEFT.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Counts down the current beep, silencing the speaker once it has run out.
+/// Call once per timer tick.
+pub fn tick() {
+    let left = BEEP_TICKS_LEFT.load(Ordering::Relaxed);
+    if left == 0 {
+        return;
+    }
+    BEEP_TICKS_LEFT.store(left - 1, Ordering::Relaxed);
+    if left == 1 {
+        silence();
+    }
+}